@@ -2,7 +2,8 @@ use std::str::FromStr;
 
 use anchor_client::{
     solana_sdk::{
-        commitment_config::CommitmentConfig, pubkey::Pubkey, signature::read_keypair_file,
+        commitment_config::CommitmentConfig, native_token::LAMPORTS_PER_SOL, pubkey::Pubkey,
+        signature::read_keypair_file,
     },
     Client,
 };
@@ -140,6 +141,52 @@ impl FromStr for ConfigPreset {
     }
 }
 
+/// Fee tier in `min_duration:fee_in_lamports:fee_in_token_numerator:fee_in_token_denominator:fee_in_stable:fee_in_usd_cents` form.
+#[derive(Debug)]
+struct FeeTierArg(locker::FeeTier);
+
+impl FromStr for FeeTierArg {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s.split(':').collect::<Vec<_>>();
+        if parts.len() != 6 {
+            return Err(anyhow!(
+                "fee tier should be min_duration:fee_in_lamports:numerator:denominator:fee_in_stable:fee_in_usd_cents"
+            ));
+        }
+
+        Ok(Self(locker::FeeTier {
+            min_duration: parts[0].parse()?,
+            fee_in_lamports: parts[1].parse()?,
+            fee_in_token_numerator: parts[2].parse()?,
+            fee_in_token_denominator: parts[3].parse()?,
+            fee_in_stable: parts[4].parse()?,
+            fee_in_usd_cents: parts[5].parse()?,
+        }))
+    }
+}
+
+/// Fee recipient in `wallet:bps` form.
+#[derive(Debug)]
+struct FeeRecipientArg(locker::FeeRecipient);
+
+impl FromStr for FeeRecipientArg {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s.split(':').collect::<Vec<_>>();
+        if parts.len() != 2 {
+            return Err(anyhow!("fee recipient should be wallet:bps"));
+        }
+
+        Ok(Self(locker::FeeRecipient {
+            wallet: parts[0].parse()?,
+            bps: parts[1].parse()?,
+        }))
+    }
+}
+
 #[derive(Debug, StructOpt)]
 enum LockerCmd {
     /// Add some token to whitelist.
@@ -158,6 +205,134 @@ enum LockerCmd {
     },
     /// Show config for program.
     ShowConfig {},
+    /// Propose new admin, it takes effect after new admin accepts it.
+    ProposeAdmin {
+        #[structopt(long)]
+        new_admin: Pubkey,
+    },
+    /// Accept admin role proposed to payer.
+    AcceptAdmin {},
+    /// Cancel pending admin proposal.
+    CancelAdminProposal {},
+    /// Pause lockers creation and changes, withdrawals stay available by default.
+    Pause {
+        #[structopt(long)]
+        block_withdrawals: bool,
+    },
+    /// Unpause locker program.
+    Unpause {},
+    /// Replace fee tiers for long locks, no tiers means base fees only.
+    SetFeeTiers {
+        #[structopt(long = "tier")]
+        tiers: Vec<FeeTierArg>,
+    },
+    /// Set the flat fee in SOL, in lamports.
+    SetSolFee {
+        #[structopt(long)]
+        lamports: u64,
+    },
+    /// Accept the flat fee in stable token instead of SOL, amount is in mint units.
+    /// The fee in stable token is turned off if there's no mint.
+    SetStableFee {
+        #[structopt(long)]
+        mint: Option<Pubkey>,
+        #[structopt(long)]
+        amount: u64,
+    },
+    /// Price the SOL fee in USD cents using the price feed, the fee
+    /// in lamports is used again if there's no price feed.
+    SetUsdFee {
+        #[structopt(long)]
+        cents: u64,
+        #[structopt(long)]
+        price_feed: Option<Pubkey>,
+        /// Max age of the price in seconds.
+        #[structopt(long, default_value = "60")]
+        max_age: i64,
+        /// Max confidence interval relative to the price.
+        #[structopt(long, default_value = "100")]
+        max_confidence_bps: u16,
+    },
+    /// Upgrade config created before accounts had a version.
+    MigrateConfig {},
+    /// Upgrade mint info created before accounts had a version.
+    MigrateMintInfo {
+        #[structopt(long)]
+        mint: Pubkey,
+    },
+    /// Replace wallets sharing the fees, fee wallet gets the rest.
+    SetFeeRecipients {
+        #[structopt(long = "recipient")]
+        recipients: Vec<FeeRecipientArg>,
+    },
+    /// Set part of the fees paid to referrers, at most 3000 bps.
+    SetReferralRate {
+        #[structopt(long)]
+        bps: u16,
+    },
+    /// Let the wallet get part of the fees of lockers it refers.
+    AddReferrer {
+        #[structopt(long)]
+        wallet: Pubkey,
+    },
+    /// Stop paying referral fees to the wallet.
+    RemoveReferrer {
+        #[structopt(long)]
+        wallet: Pubkey,
+    },
+    /// Let the wallet pay only part of the fees, 10000 bps means no fees.
+    AddFeeExemption {
+        #[structopt(long)]
+        wallet: Pubkey,
+        #[structopt(long)]
+        discount_bps: u16,
+    },
+    /// Change fee discount for the wallet.
+    UpdateFeeExemption {
+        #[structopt(long)]
+        wallet: Pubkey,
+        #[structopt(long)]
+        discount_bps: u16,
+    },
+    /// Make the wallet pay full fees again.
+    RemoveFeeExemption {
+        #[structopt(long)]
+        wallet: Pubkey,
+    },
+    /// Show fee exemption for the wallet.
+    ShowFeeExemption {
+        #[structopt(long)]
+        wallet: Pubkey,
+    },
+    /// Show lock stats for the mint.
+    ShowMintInfo {
+        #[structopt(long)]
+        mint: Pubkey,
+    },
+    /// Show locker created by the wallet for the mint.
+    ShowLocker {
+        #[structopt(long)]
+        creator: Pubkey,
+        #[structopt(long)]
+        mint: Pubkey,
+        #[structopt(long, default_value = "0")]
+        nonce: u64,
+    },
+    /// Let the owner withdraw everything from the locker after the delay.
+    ApproveEmergencyUnlock {
+        #[structopt(long)]
+        locker: Pubkey,
+    },
+    /// Drop emergency unlock request for the locker.
+    CancelEmergencyUnlock {
+        #[structopt(long)]
+        locker: Pubkey,
+    },
+    /// Show emergency unlock request for the locker.
+    ShowEmergencyUnlock {
+        #[structopt(long)]
+        locker: Pubkey,
+    },
 }
 
 fn main() -> Result<()> {
@@ -280,7 +455,7 @@ fn main() -> Result<()> {
 
                 let args = match preset {
                     ConfigPreset::TokenLocker => locker::CreateConfigArgs {
-                        fee_in_sol: 1,
+                        fee_in_lamports: LAMPORTS_PER_SOL,
                         fee_in_token_numerator: 35,
                         fee_in_token_denominator: 10000,
                         mint_info_permissioned: false,
@@ -288,7 +463,7 @@ fn main() -> Result<()> {
                         bump,
                     },
                     ConfigPreset::LPLocker => locker::CreateConfigArgs {
-                        fee_in_sol: 1,
+                        fee_in_lamports: LAMPORTS_PER_SOL,
                         fee_in_token_numerator: 10,
                         fee_in_token_denominator: 1000,
                         mint_info_permissioned: true,
@@ -319,8 +494,451 @@ fn main() -> Result<()> {
                 let config: locker::Config = client.account(config)?;
                 println!("{:#?}", config);
             }
+            LockerCmd::ProposeAdmin { new_admin } => {
+                let (config, _bump) =
+                    Pubkey::find_program_address(&["config".as_ref()], &client.id());
+
+                let r = client
+                    .request()
+                    .accounts(locker::accounts::ProposeAdmin {
+                        admin: client.payer(),
+                        config,
+                        new_admin,
+                    })
+                    .args(locker::instruction::ProposeAdmin {})
+                    .signer(&payer_copy)
+                    .send()?;
+
+                println!("Result:\n{}", r);
+            }
+            LockerCmd::AcceptAdmin {} => {
+                let (config, _bump) =
+                    Pubkey::find_program_address(&["config".as_ref()], &client.id());
+
+                let r = client
+                    .request()
+                    .accounts(locker::accounts::AcceptAdmin {
+                        new_admin: client.payer(),
+                        config,
+                    })
+                    .args(locker::instruction::AcceptAdmin {})
+                    .signer(&payer_copy)
+                    .send()?;
+
+                println!("Result:\n{}", r);
+            }
+            LockerCmd::CancelAdminProposal {} => {
+                let (config, _bump) =
+                    Pubkey::find_program_address(&["config".as_ref()], &client.id());
+
+                let r = client
+                    .request()
+                    .accounts(locker::accounts::CancelAdminProposal {
+                        admin: client.payer(),
+                        config,
+                    })
+                    .args(locker::instruction::CancelAdminProposal {})
+                    .signer(&payer_copy)
+                    .send()?;
+
+                println!("Result:\n{}", r);
+            }
+            LockerCmd::Pause { block_withdrawals } => {
+                update_config(
+                    &client,
+                    &payer_copy,
+                    locker::UpdateConfigArgs {
+                        paused: Some(true),
+                        allow_withdrawals_when_paused: Some(!block_withdrawals),
+                        ..Default::default()
+                    },
+                )?;
+            }
+            LockerCmd::Unpause {} => {
+                update_config(
+                    &client,
+                    &payer_copy,
+                    locker::UpdateConfigArgs {
+                        paused: Some(false),
+                        ..Default::default()
+                    },
+                )?;
+            }
+            LockerCmd::SetFeeTiers { tiers } => {
+                update_config(
+                    &client,
+                    &payer_copy,
+                    locker::UpdateConfigArgs {
+                        fee_tiers: Some(tiers.into_iter().map(|tier| tier.0).collect()),
+                        ..Default::default()
+                    },
+                )?;
+            }
+            LockerCmd::SetSolFee { lamports } => {
+                update_config(
+                    &client,
+                    &payer_copy,
+                    locker::UpdateConfigArgs {
+                        fee_in_lamports: Some(lamports),
+                        ..Default::default()
+                    },
+                )?;
+            }
+            LockerCmd::SetStableFee { mint, amount } => {
+                update_config(
+                    &client,
+                    &payer_copy,
+                    locker::UpdateConfigArgs {
+                        fee_in_stable: Some(amount),
+                        stable_fee_mint: Some(mint.unwrap_or_default()),
+                        ..Default::default()
+                    },
+                )?;
+            }
+            LockerCmd::SetUsdFee {
+                cents,
+                price_feed,
+                max_age,
+                max_confidence_bps,
+            } => {
+                update_config(
+                    &client,
+                    &payer_copy,
+                    locker::UpdateConfigArgs {
+                        fee_in_usd_cents: Some(cents),
+                        sol_price_feed: Some(locker::PriceFeed {
+                            account: price_feed.unwrap_or_default(),
+                            max_age,
+                            max_confidence_bps,
+                        }),
+                        ..Default::default()
+                    },
+                )?;
+            }
+            LockerCmd::MigrateConfig {} => {
+                let (config, _bump) =
+                    Pubkey::find_program_address(&["config".as_ref()], &client.id());
+
+                let r = client
+                    .request()
+                    .accounts(locker::accounts::MigrateConfig {
+                        admin: payer_copy.pubkey(),
+                        config,
+                        system_program: anchor_client::solana_sdk::system_program::id(),
+                    })
+                    .args(locker::instruction::MigrateConfig {})
+                    .signer(&payer_copy)
+                    .send()?;
+
+                println!("Result:\n{}", r);
+            }
+            LockerCmd::MigrateMintInfo { mint } => {
+                let (config, _bump) =
+                    Pubkey::find_program_address(&["config".as_ref()], &client.id());
+                let (mint_info, _bump) =
+                    Pubkey::find_program_address(&[mint.as_ref()], &client.id());
+
+                let r = client
+                    .request()
+                    .accounts(locker::accounts::MigrateMintInfo {
+                        payer: payer_copy.pubkey(),
+                        mint_info,
+                        mint,
+                        config,
+                        system_program: anchor_client::solana_sdk::system_program::id(),
+                    })
+                    .args(locker::instruction::MigrateMintInfo {})
+                    .signer(&payer_copy)
+                    .send()?;
+
+                println!("Result:\n{}", r);
+            }
+            LockerCmd::SetFeeRecipients { recipients } => {
+                update_config(
+                    &client,
+                    &payer_copy,
+                    locker::UpdateConfigArgs {
+                        fee_recipients: Some(
+                            recipients
+                                .into_iter()
+                                .map(|recipient| recipient.0)
+                                .collect(),
+                        ),
+                        ..Default::default()
+                    },
+                )?;
+            }
+            LockerCmd::SetReferralRate { bps } => {
+                if bps > locker::Config::MAX_REFERRAL_BPS {
+                    return Err(anyhow!(
+                        "referral rate can't be more than {} bps",
+                        locker::Config::MAX_REFERRAL_BPS
+                    ));
+                }
+                update_config(
+                    &client,
+                    &payer_copy,
+                    locker::UpdateConfigArgs {
+                        referral_bps: Some(bps),
+                        ..Default::default()
+                    },
+                )?;
+            }
+            LockerCmd::AddReferrer { wallet } => {
+                let (config, _bump) =
+                    Pubkey::find_program_address(&["config".as_ref()], &client.id());
+                let (referrer, bump) = Pubkey::find_program_address(
+                    &["referrer".as_ref(), wallet.as_ref()],
+                    &client.id(),
+                );
+
+                let r = client
+                    .request()
+                    .accounts(locker::accounts::AddReferrer {
+                        admin: client.payer(),
+                        config,
+                        wallet,
+                        referrer,
+                        system_program: anchor_client::solana_sdk::system_program::id(),
+                    })
+                    .args(locker::instruction::AddReferrer { bump })
+                    .signer(&payer_copy)
+                    .send()?;
+
+                println!("Result:\n{}", r);
+            }
+            LockerCmd::RemoveReferrer { wallet } => {
+                let (config, _bump) =
+                    Pubkey::find_program_address(&["config".as_ref()], &client.id());
+                let (referrer, _bump) = Pubkey::find_program_address(
+                    &["referrer".as_ref(), wallet.as_ref()],
+                    &client.id(),
+                );
+
+                let r = client
+                    .request()
+                    .accounts(locker::accounts::RemoveReferrer {
+                        admin: client.payer(),
+                        config,
+                        referrer,
+                    })
+                    .args(locker::instruction::RemoveReferrer {})
+                    .signer(&payer_copy)
+                    .send()?;
+
+                println!("Result:\n{}", r);
+            }
+            LockerCmd::AddFeeExemption {
+                wallet,
+                discount_bps,
+            } => {
+                let (config, _bump) =
+                    Pubkey::find_program_address(&["config".as_ref()], &client.id());
+                let (fee_exemption, bump) = Pubkey::find_program_address(
+                    &["fee_exemption".as_ref(), wallet.as_ref()],
+                    &client.id(),
+                );
+
+                let r = client
+                    .request()
+                    .accounts(locker::accounts::AddFeeExemption {
+                        admin: client.payer(),
+                        config,
+                        wallet,
+                        fee_exemption,
+                        system_program: anchor_client::solana_sdk::system_program::id(),
+                    })
+                    .args(locker::instruction::AddFeeExemption {
+                        args: locker::AddFeeExemptionArgs { discount_bps, bump },
+                    })
+                    .signer(&payer_copy)
+                    .send()?;
+
+                println!("Result:\n{}", r);
+            }
+            LockerCmd::UpdateFeeExemption {
+                wallet,
+                discount_bps,
+            } => {
+                let (config, _bump) =
+                    Pubkey::find_program_address(&["config".as_ref()], &client.id());
+                let (fee_exemption, _bump) = Pubkey::find_program_address(
+                    &["fee_exemption".as_ref(), wallet.as_ref()],
+                    &client.id(),
+                );
+
+                let r = client
+                    .request()
+                    .accounts(locker::accounts::UpdateFeeExemption {
+                        admin: client.payer(),
+                        config,
+                        fee_exemption,
+                    })
+                    .args(locker::instruction::UpdateFeeExemption { discount_bps })
+                    .signer(&payer_copy)
+                    .send()?;
+
+                println!("Result:\n{}", r);
+            }
+            LockerCmd::RemoveFeeExemption { wallet } => {
+                let (config, _bump) =
+                    Pubkey::find_program_address(&["config".as_ref()], &client.id());
+                let (fee_exemption, _bump) = Pubkey::find_program_address(
+                    &["fee_exemption".as_ref(), wallet.as_ref()],
+                    &client.id(),
+                );
+
+                let r = client
+                    .request()
+                    .accounts(locker::accounts::RemoveFeeExemption {
+                        admin: client.payer(),
+                        config,
+                        fee_exemption,
+                    })
+                    .args(locker::instruction::RemoveFeeExemption {})
+                    .signer(&payer_copy)
+                    .send()?;
+
+                println!("Result:\n{}", r);
+            }
+            LockerCmd::ShowFeeExemption { wallet } => {
+                let (fee_exemption, _bump) = Pubkey::find_program_address(
+                    &["fee_exemption".as_ref(), wallet.as_ref()],
+                    &client.id(),
+                );
+
+                let fee_exemption: locker::FeeExemption = client.account(fee_exemption)?;
+                println!("{:#?}", fee_exemption);
+            }
+            LockerCmd::ShowMintInfo { mint } => {
+                let (mint_info, _bump) =
+                    Pubkey::find_program_address(&[mint.as_ref()], &client.id());
+
+                let mint_info: locker::MintInfo = client.account(mint_info)?;
+                println!("{:#?}", mint_info);
+
+                // supply goes right after COption<Pubkey> mint authority
+                // in both token programs
+                let mint_data = client.rpc().get_account_data(&mint)?;
+                let mut supply = [0; 8];
+                supply.copy_from_slice(&mint_data[36..44]);
+                let supply = u64::from_le_bytes(supply);
+
+                println!("Lockers: {}", mint_info.locker_count());
+                // vested tokens of lockers with emission count until withdrawn
+                println!(
+                    "Total locked (or vested but not withdrawn): {} of {} supply",
+                    mint_info.total_locked(),
+                    supply
+                );
+                if supply > 0 {
+                    println!(
+                        "Locked share: {:.2}%",
+                        mint_info.total_locked() as f64 * 100.0 / supply as f64
+                    );
+                }
+                println!("Revocable locked: {}", mint_info.revocable_locked());
+                if let Some(date) = mint_info.unlock_date_lower_bound() {
+                    println!("No locker unlocks before: {}", date);
+                }
+                if let Some(date) = mint_info.average_unlock_date() {
+                    println!("Average unlock date: {}", date);
+                }
+            }
+            LockerCmd::ShowLocker {
+                creator,
+                mint,
+                nonce,
+            } => {
+                let (address, _bump) =
+                    locker::Locker::find_address(&creator, &mint, nonce, &client.id());
+
+                let locker: locker::Locker = client.account(address)?;
+                println!("Locker Address: {}", address);
+                println!("{:#?}", locker);
+            }
+            LockerCmd::ApproveEmergencyUnlock { locker } => {
+                let (config, _bump) =
+                    Pubkey::find_program_address(&["config".as_ref()], &client.id());
+                let (emergency_unlock, _bump) = Pubkey::find_program_address(
+                    &["emergency_unlock".as_ref(), locker.as_ref()],
+                    &client.id(),
+                );
+
+                let r = client
+                    .request()
+                    .accounts(locker::accounts::ApproveEmergencyUnlock {
+                        admin: client.payer(),
+                        config,
+                        emergency_unlock,
+                        clock: anchor_client::solana_sdk::sysvar::clock::id(),
+                    })
+                    .args(locker::instruction::ApproveEmergencyUnlock {})
+                    .signer(&payer_copy)
+                    .send()?;
+
+                println!("Result:\n{}", r);
+            }
+            LockerCmd::CancelEmergencyUnlock { locker } => {
+                let (config, _bump) =
+                    Pubkey::find_program_address(&["config".as_ref()], &client.id());
+                let (emergency_unlock, _bump) = Pubkey::find_program_address(
+                    &["emergency_unlock".as_ref(), locker.as_ref()],
+                    &client.id(),
+                );
+                let request: locker::EmergencyUnlock = client.account(emergency_unlock)?;
+
+                let r = client
+                    .request()
+                    .accounts(locker::accounts::CancelEmergencyUnlock {
+                        authority: client.payer(),
+                        locker,
+                        config,
+                        emergency_unlock,
+                        requester: request.requester(),
+                    })
+                    .args(locker::instruction::CancelEmergencyUnlock {})
+                    .signer(&payer_copy)
+                    .send()?;
+
+                println!("Result:\n{}", r);
+            }
+            LockerCmd::ShowEmergencyUnlock { locker } => {
+                let (emergency_unlock, _bump) = Pubkey::find_program_address(
+                    &["emergency_unlock".as_ref(), locker.as_ref()],
+                    &client.id(),
+                );
+
+                let request: locker::EmergencyUnlock = client.account(emergency_unlock)?;
+                println!("{:#?}", request);
+            }
         },
     }
 
     Ok(())
 }
+
+fn update_config(
+    client: &anchor_client::Program,
+    payer: &Keypair,
+    args: locker::UpdateConfigArgs,
+) -> Result<()> {
+    let (config, _bump) = Pubkey::find_program_address(&["config".as_ref()], &client.id());
+    let config_account: locker::Config = client.account(config)?;
+
+    let r = client
+        .request()
+        .accounts(locker::accounts::UpdateConfig {
+            admin: client.payer(),
+            config,
+            fee_wallet: config_account.fee_wallet(),
+            country_list: config_account.country_list(),
+        })
+        .args(locker::instruction::UpdateConfig { args })
+        .signer(payer)
+        .send()?;
+
+    println!("Result:\n{}", r);
+
+    Ok(())
+}
@@ -42,6 +42,12 @@ pub mod country_list {
                 // unwrap since we have found index of the entry
                 let country_ban = country_banlist.countries.get_mut(idx).unwrap();
                 country_ban.banned = value;
+
+                emit!(BanFlipped {
+                    country_banlist: country_banlist.key(),
+                    country: array,
+                    banned: value,
+                });
             }
             Err(_) => {
                 return Err(ErrorCode::UnknownCountry.into());
@@ -52,6 +58,13 @@ pub mod country_list {
     }
 }
 
+#[event]
+pub struct BanFlipped {
+    pub country_banlist: Pubkey,
+    pub country: [u8; 2],
+    pub banned: bool,
+}
+
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
 pub struct CountryData {
     pub code: [u8; 2],
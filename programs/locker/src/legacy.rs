@@ -0,0 +1,88 @@
+//! Layouts of accounts created before they had a version.
+//!
+//! They are only read to migrate the accounts to the current layouts, see
+//! `migrate_*` instructions.
+
+use anchor_lang::{prelude::*, Discriminator};
+
+use crate::{ErrorCode, Result};
+
+#[derive(AnchorDeserialize)]
+pub struct Config {
+    pub admin: Pubkey,
+    /// Whole SOL.
+    pub fee_in_sol: u64,
+    pub fee_in_token_numerator: u64,
+    pub fee_in_token_denominator: u64,
+    pub mint_info_permissioned: bool,
+    pub has_linear_emission: bool,
+    pub fee_wallet: Pubkey,
+    pub country_list: Pubkey,
+    pub bump: u8,
+}
+
+#[derive(AnchorDeserialize)]
+pub struct Locker {
+    pub owner: Pubkey,
+    pub country_code: [u8; 2],
+    pub current_unlock_date: i64,
+    pub start_emission: Option<i64>,
+    /// Withdrawals were not tracked, what's left is the amount in the vault.
+    pub deposited_amount: u64,
+    pub vault: Pubkey,
+    pub vault_bump: u8,
+}
+
+#[derive(AnchorDeserialize)]
+pub struct MintInfo {
+    pub bump: u8,
+    pub fee_paid: bool,
+}
+
+/// Accounts having the version right after the discriminator.
+pub trait Versioned: Discriminator {
+    const VERSION: u8;
+    /// Smallest size of the account with a version, accounts created
+    /// before that are all shorter.
+    const MIN_LEN: usize;
+}
+
+impl Versioned for crate::Config {
+    const VERSION: u8 = crate::Config::VERSION;
+    const MIN_LEN: usize = crate::Config::LEN;
+}
+
+impl Versioned for crate::Locker {
+    const VERSION: u8 = crate::Locker::VERSION;
+    const MIN_LEN: usize = crate::Locker::LEN;
+}
+
+impl Versioned for crate::MintInfo {
+    const VERSION: u8 = crate::MintInfo::VERSION;
+    const MIN_LEN: usize = crate::MintInfo::LEN;
+}
+
+/// Reads the legacy layout `L` of an account of type `T`, fails if the
+/// account has the current layout already.
+pub fn load<T, L>(info: &AccountInfo) -> Result<L>
+where
+    T: Versioned,
+    L: AnchorDeserialize,
+{
+    require!(info.owner == &crate::ID, InvalidMigration);
+
+    let data = info.try_borrow_data()?;
+    require!(
+        data.len() >= 8 && data[..8] == T::discriminator(),
+        InvalidMigration
+    );
+    if data.len() >= T::MIN_LEN {
+        require!(data[8] != T::VERSION, AlreadyMigrated);
+        // there's no migration from versions newer than the program knows
+        return Err(ErrorCode::InvalidMigration.into());
+    }
+
+    let legacy = L::deserialize(&mut &data[8..]).map_err(|_| ErrorCode::InvalidMigration)?;
+
+    Ok(legacy)
+}
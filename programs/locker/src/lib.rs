@@ -2,21 +2,21 @@ use std::ops::DerefMut;
 
 use anchor_lang::{
     prelude::*,
-    solana_program::{
-        self,
-        log::{sol_log, sol_log_64},
-    },
+    solana_program::{self, log::sol_log},
     AccountsClose,
 };
-use anchor_spl::{
-    associated_token::get_associated_token_address,
-    token::{self, CloseAccount, Mint, Token, TokenAccount, Transfer},
-};
 
 use az::CheckedAs;
 
+mod legacy;
+mod price_feed;
+mod test_instructions;
+mod token_interface;
+
 declare_id!("Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS");
 
+pub const BASIS_POINTS: u64 = 10_000;
+
 #[error]
 pub enum ErrorCode {
     #[msg("The given unlock date is in the past")]
@@ -34,6 +34,68 @@ pub enum ErrorCode {
     InvalidCountry,
     InitMintInfoNotAuthorized,
     LinearEmissionDisabled,
+    InvalidCliff,
+    InvalidSchedule,
+    #[msg("The locker program is paused")]
+    Paused,
+    InvalidDiscount,
+    InvalidFeeTiers,
+    InvalidTokenAccount,
+    InvalidMint,
+    #[msg("The mint has an extension which makes locking its tokens unsafe")]
+    UnsupportedMintExtension,
+    InvalidReceipt,
+    #[msg("The signer does not hold the locker receipt")]
+    ReceiptNotHeld,
+    #[msg("Locker with receipt changes hands with the receipt token")]
+    LockerHasReceipt,
+    #[msg("Lockers with emission can be merged only if they vest the same way")]
+    InvalidMerge,
+    InvalidBeneficiaries,
+    #[msg("The signer is not a beneficiary of the locker")]
+    NotBeneficiary,
+    #[msg("The operation is not supported for locker with beneficiaries")]
+    LockerHasBeneficiaries,
+    #[msg("Target wallet is not owned by the beneficiary")]
+    InvalidTargetWallet,
+    InvalidDelegate,
+    #[msg("Referrer account is missing or invalid")]
+    InvalidReferrer,
+    InvalidReferralRate,
+    InvalidFeeRecipients,
+    #[msg("Fee recipient account is missing or invalid")]
+    InvalidFeeRecipient,
+    #[msg("Fees in stable token are not accepted")]
+    StableFeeDisabled,
+    #[msg("Stable fee account is missing or invalid")]
+    InvalidStableFeeAccount,
+    #[msg("Price feed account is missing or invalid")]
+    InvalidPriceFeed,
+    #[msg("Price feed has no recent price")]
+    StalePrice,
+    #[msg("Price feed confidence interval is too wide")]
+    PriceTooUncertain,
+    #[msg("The account has the current layout already")]
+    AlreadyMigrated,
+    InvalidMigration,
+    #[msg("Emergency unlock is not approved or its delay has not passed yet")]
+    EmergencyUnlockNotReady,
+    #[msg("Emergency unlock is approved already")]
+    EmergencyUnlockAlreadyApproved,
+    #[msg("The instruction is available only in test builds")]
+    TestInstructionsDisabled,
+    #[msg("Lockers with tranche schedule can't be relocked")]
+    CannotRelockSchedule,
+    #[msg("Fee in tokens must be a fraction not above one")]
+    InvalidTokenFee,
+    #[msg("Only lockers with emission can be revocable")]
+    RevocableWithoutEmission,
+    #[msg("Revocable lockers can't be incremented")]
+    CannotIncrementRevocable,
+    #[msg("Fee in stable token replaces fee in SOL, so both should be set")]
+    InvalidFeeOption,
+    #[msg("The locker is unlocked already, withdraw the funds instead")]
+    AlreadyUnlocked,
 }
 
 #[program]
@@ -41,45 +103,155 @@ pub mod locker {
     use super::*;
 
     pub fn init_config(ctx: Context<InitConfig>, args: CreateConfigArgs) -> Result<()> {
+        validate_token_fee(args.fee_in_token_numerator, args.fee_in_token_denominator)?;
+
         let config = ctx.accounts.config.deref_mut();
 
         *config = Config {
+            version: Config::VERSION,
             admin: ctx.accounts.admin.key(),
-            fee_in_sol: args.fee_in_sol,
+            pending_admin: None,
+            fee_in_lamports: args.fee_in_lamports,
             fee_in_token_numerator: args.fee_in_token_numerator,
             fee_in_token_denominator: args.fee_in_token_denominator,
+            fee_in_stable: 0,
+            stable_fee_mint: None,
+            fee_in_usd_cents: 0,
+            sol_price_feed: None,
+            fee_tiers: Vec::new(),
+            referral_bps: 0,
+            fee_recipients: Vec::new(),
             mint_info_permissioned: args.mint_info_permissioned,
             has_linear_emission: args.has_linear_emission,
             fee_wallet: ctx.accounts.fee_wallet.key(),
             country_list: ctx.accounts.country_list.key(),
+            paused: false,
+            allow_withdrawals_when_paused: true,
             bump: args.bump,
+            reserved: [0; 64],
         };
 
+        emit!(config.to_event());
+
         Ok(())
     }
 
     pub fn update_config(ctx: Context<UpdateConfig>, args: UpdateConfigArgs) -> Result<()> {
         let config = &mut ctx.accounts.config;
         let UpdateConfigArgs {
-            fee_in_sol,
+            fee_in_lamports,
             fee_in_token_numerator,
             fee_in_token_denominator,
+            fee_in_stable,
+            stable_fee_mint,
+            fee_in_usd_cents,
+            sol_price_feed,
+            fee_tiers,
+            referral_bps,
+            fee_recipients,
             mint_info_permissioned,
             has_linear_emission,
+            paused,
+            allow_withdrawals_when_paused,
         } = args;
 
-        config.fee_in_sol = fee_in_sol.unwrap_or(config.fee_in_sol);
+        config.fee_in_lamports = fee_in_lamports.unwrap_or(config.fee_in_lamports);
         config.fee_in_token_numerator =
             fee_in_token_numerator.unwrap_or(config.fee_in_token_numerator);
         config.fee_in_token_denominator =
             fee_in_token_denominator.unwrap_or(config.fee_in_token_denominator);
+        validate_token_fee(
+            config.fee_in_token_numerator,
+            config.fee_in_token_denominator,
+        )?;
+        config.fee_in_stable = fee_in_stable.unwrap_or(config.fee_in_stable);
+        if let Some(stable_fee_mint) = stable_fee_mint {
+            config.stable_fee_mint = if stable_fee_mint == Pubkey::default() {
+                None
+            } else {
+                Some(stable_fee_mint)
+            };
+        }
+        config.fee_in_usd_cents = fee_in_usd_cents.unwrap_or(config.fee_in_usd_cents);
+        if let Some(sol_price_feed) = sol_price_feed {
+            config.sol_price_feed = if sol_price_feed.account == Pubkey::default() {
+                None
+            } else {
+                require!(
+                    sol_price_feed.max_age > 0
+                        && sol_price_feed.max_confidence_bps as u64 <= BASIS_POINTS,
+                    InvalidPriceFeed
+                );
+                Some(sol_price_feed)
+            };
+        }
+        if let Some(fee_tiers) = fee_tiers {
+            validate_fee_tiers(&fee_tiers)?;
+            config.fee_tiers = fee_tiers;
+        }
+        if let Some(referral_bps) = referral_bps {
+            require!(
+                referral_bps <= Config::MAX_REFERRAL_BPS,
+                InvalidReferralRate
+            );
+            config.referral_bps = referral_bps;
+        }
+        if let Some(fee_recipients) = fee_recipients {
+            validate_fee_recipients(&fee_recipients)?;
+            config.fee_recipients = fee_recipients;
+        }
         config.mint_info_permissioned =
             mint_info_permissioned.unwrap_or(config.mint_info_permissioned);
         config.has_linear_emission = has_linear_emission.unwrap_or(config.has_linear_emission);
+        config.paused = paused.unwrap_or(config.paused);
+        config.allow_withdrawals_when_paused =
+            allow_withdrawals_when_paused.unwrap_or(config.allow_withdrawals_when_paused);
 
         config.fee_wallet = ctx.accounts.fee_wallet.key();
         config.country_list = ctx.accounts.country_list.key();
 
+        emit!(config.to_event());
+
+        Ok(())
+    }
+
+    pub fn propose_admin(ctx: Context<ProposeAdmin>) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+
+        config.pending_admin = Some(ctx.accounts.new_admin.key());
+
+        emit!(AdminProposed {
+            admin: config.admin,
+            pending_admin: ctx.accounts.new_admin.key(),
+        });
+
+        Ok(())
+    }
+
+    pub fn accept_admin(ctx: Context<AcceptAdmin>) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+
+        let old_admin = config.admin;
+        config.admin = ctx.accounts.new_admin.key();
+        config.pending_admin = None;
+
+        emit!(AdminAccepted {
+            old_admin,
+            new_admin: config.admin,
+        });
+
+        Ok(())
+    }
+
+    pub fn cancel_admin_proposal(ctx: Context<CancelAdminProposal>) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+
+        config.pending_admin = None;
+
+        emit!(AdminProposalCancelled {
+            admin: config.admin,
+        });
+
         Ok(())
     }
 
@@ -95,7 +267,7 @@ pub mod locker {
 
         *mint_info = MintInfo {
             bump,
-            fee_paid: false,
+            ..Default::default()
         };
 
         sol_log("Initialize mint info");
@@ -103,6 +275,84 @@ pub mod locker {
         Ok(())
     }
 
+    pub fn add_fee_exemption(
+        ctx: Context<AddFeeExemption>,
+        args: AddFeeExemptionArgs,
+    ) -> Result<()> {
+        require!(args.discount_bps as u64 <= BASIS_POINTS, InvalidDiscount);
+
+        let fee_exemption = ctx.accounts.fee_exemption.deref_mut();
+
+        *fee_exemption = FeeExemption {
+            wallet: ctx.accounts.wallet.key(),
+            discount_bps: args.discount_bps,
+            bump: args.bump,
+        };
+
+        emit!(FeeExemptionUpdated {
+            wallet: fee_exemption.wallet,
+            discount_bps: fee_exemption.discount_bps,
+        });
+
+        Ok(())
+    }
+
+    pub fn update_fee_exemption(ctx: Context<UpdateFeeExemption>, discount_bps: u16) -> Result<()> {
+        require!(discount_bps as u64 <= BASIS_POINTS, InvalidDiscount);
+
+        let fee_exemption = &mut ctx.accounts.fee_exemption;
+        fee_exemption.discount_bps = discount_bps;
+
+        emit!(FeeExemptionUpdated {
+            wallet: fee_exemption.wallet,
+            discount_bps,
+        });
+
+        Ok(())
+    }
+
+    pub fn remove_fee_exemption(ctx: Context<RemoveFeeExemption>) -> Result<()> {
+        let fee_exemption = &ctx.accounts.fee_exemption;
+
+        fee_exemption.close(ctx.accounts.admin.to_account_info())?;
+
+        emit!(FeeExemptionUpdated {
+            wallet: fee_exemption.wallet,
+            discount_bps: 0,
+        });
+
+        Ok(())
+    }
+
+    pub fn add_referrer(ctx: Context<AddReferrer>, bump: u8) -> Result<()> {
+        let referrer = ctx.accounts.referrer.deref_mut();
+
+        *referrer = Referrer {
+            wallet: ctx.accounts.wallet.key(),
+            bump,
+        };
+
+        emit!(ReferrerUpdated {
+            wallet: referrer.wallet,
+            registered: true,
+        });
+
+        Ok(())
+    }
+
+    pub fn remove_referrer(ctx: Context<RemoveReferrer>) -> Result<()> {
+        let referrer = &ctx.accounts.referrer;
+
+        referrer.close(ctx.accounts.admin.to_account_info())?;
+
+        emit!(ReferrerUpdated {
+            wallet: referrer.wallet,
+            registered: false,
+        });
+
+        Ok(())
+    }
+
     pub fn create_locker<'info>(
         ctx: Context<'_, '_, '_, 'info, CreateLocker<'info>>,
         args: CreateLockerArgs,
@@ -115,15 +365,64 @@ pub mod locker {
         require!(args.unlock_date < 10000000000, InvalidTimestamp);
 
         let config = &ctx.accounts.config;
+        require!(!config.paused, Paused);
+
+        // stable fee would be silently ignored for fees in tokens
+        require!(!args.fee_in_stable || args.fee_in_sol, InvalidFeeOption);
 
         if !config.has_linear_emission {
             require!(args.start_emission.is_none(), LinearEmissionDisabled);
+            require!(args.cliff.is_none(), LinearEmissionDisabled);
+            require!(args.schedule.is_empty(), LinearEmissionDisabled);
         }
 
         if let Some(start_emission) = args.start_emission {
             require!(args.unlock_date > start_emission, InvalidPeriod);
         }
 
+        if let Some(cliff) = args.cliff {
+            let start_emission = args.start_emission.ok_or(ErrorCode::InvalidCliff)?;
+            require!(
+                cliff.date >= start_emission && cliff.date < args.unlock_date,
+                InvalidCliff
+            );
+            require!(cliff.bps as u64 <= BASIS_POINTS, InvalidCliff);
+        }
+
+        if !args.schedule.is_empty() {
+            require!(
+                args.start_emission.is_none() && args.cliff.is_none(),
+                InvalidSchedule
+            );
+            validate_schedule(&args.schedule, args.unlock_date)?;
+        }
+
+        // a plain time lock taken back by its creator would not be a lock
+        if args.revocable {
+            require!(
+                args.start_emission.is_some() || !args.schedule.is_empty(),
+                RevocableWithoutEmission
+            );
+        }
+
+        if !args.beneficiaries.is_empty() {
+            require!(
+                !args.receipt && args.beneficiary.is_none(),
+                InvalidBeneficiaries
+            );
+            validate_beneficiaries(&args.beneficiaries)?;
+        }
+
+        validate_referrer(
+            args.referrer,
+            &[
+                ctx.accounts.creator.key(),
+                ctx.accounts.owner.key(),
+                ctx.accounts.funding_wallet_authority.key(),
+            ],
+            ctx.remaining_accounts,
+        )?;
+
         require!(
             ctx.accounts
                 .country_banlist
@@ -131,26 +430,88 @@ pub mod locker {
             InvalidCountry
         );
 
+        let mint = token_interface::Mint::load(&ctx.accounts.mint)?;
+        mint.require_lockable()?;
+
+        let vault = token_interface::TokenAccount::load(&ctx.accounts.vault)?;
+        require!(
+            vault.mint == ctx.accounts.mint.key()
+                && vault.owner == ctx.accounts.vault_authority.key(),
+            InvalidTokenAccount
+        );
+
+        // receipt mint and owner's receipt token account go first
+        let receipt_accounts = if args.receipt {
+            require!(ctx.remaining_accounts.len() >= 2, InvalidReceipt);
+            Some((&ctx.remaining_accounts[0], &ctx.remaining_accounts[1]))
+        } else {
+            None
+        };
+
         let mint_info = &mut ctx.accounts.mint_info;
+        let discount_bps = fee_discount(ctx.remaining_accounts, &ctx.accounts.creator.key());
 
         if should_pay_in_sol(config, mint_info, args.fee_in_sol) {
-            FeeInSol {
-                fee_wallet: &ctx.accounts.fee_wallet,
-                payer: &ctx.accounts.owner,
-                config,
-                mint_info,
-                system_program: &ctx.accounts.system_program,
+            if args.fee_in_stable {
+                stable_fee(
+                    config,
+                    &ctx.accounts.creator,
+                    args.referrer.as_ref(),
+                    ctx.remaining_accounts,
+                    args.unlock_date - now,
+                    discount_bps,
+                )?
+                .pay_flat(mint_info)?;
+            } else {
+                FeeInSol {
+                    fee_wallet: &ctx.accounts.fee_wallet,
+                    referrer: args
+                        .referrer
+                        .map(|referrer| {
+                            remaining_account(
+                                ctx.remaining_accounts,
+                                &referrer,
+                                ErrorCode::InvalidReferrer,
+                            )
+                        })
+                        .transpose()?,
+                    remaining_accounts: ctx.remaining_accounts,
+                    payer: &ctx.accounts.creator,
+                    config,
+                    mint_info,
+                    now,
+                    lock_duration: args.unlock_date - now,
+                    discount_bps,
+                    system_program: &ctx.accounts.system_program,
+                }
+                .pay()?;
             }
-            .pay()?;
         }
 
         let lock_fee = if should_pay_in_tokens(config, mint_info, args.fee_in_sol) {
             FeeInTokens {
                 config,
-                funding_wallet: &mut ctx.accounts.funding_wallet,
+                funding_wallet: &ctx.accounts.funding_wallet,
                 funding_wallet_authority: &ctx.accounts.funding_wallet_authority,
                 fee_wallet: &ctx.accounts.fee_token_wallet,
+                referrer: args
+                    .referrer
+                    .map(|referrer| {
+                        remaining_token_account(
+                            ctx.remaining_accounts,
+                            &referrer,
+                            &ctx.accounts.mint,
+                            &ctx.accounts.token_program,
+                            ErrorCode::InvalidReferrer,
+                        )
+                    })
+                    .transpose()?,
+                remaining_accounts: ctx.remaining_accounts,
+                mint: &ctx.accounts.mint,
+                decimals: mint.decimals,
                 amount: args.amount,
+                lock_duration: args.unlock_date - now,
+                discount_bps,
                 token_program: &ctx.accounts.token_program,
             }
             .pay()?
@@ -158,130 +519,365 @@ pub mod locker {
             0
         };
 
-        let amount_to_lock = args.amount - lock_fee;
+        let amount_to_lock = args
+            .amount
+            .checked_sub(lock_fee)
+            .ok_or(ErrorCode::IntegerOverflow)?;
         require!(amount_to_lock > 0, NothingToLock);
 
+        let locker_key = ctx.accounts.locker.key();
         let locker = ctx.accounts.locker.deref_mut();
 
         *locker = Locker {
+            version: Locker::VERSION,
             owner: ctx.accounts.owner.key(),
+            creator: ctx.accounts.creator.key(),
+            mint: ctx.accounts.mint.key(),
+            vault: ctx.accounts.vault.key(),
+            vault_bump: args.vault_bump,
+            revocable: args.revocable,
             country_code: country_list::string_to_byte_array(&args.country_code),
             current_unlock_date: args.unlock_date,
-            start_emission: args.start_emission,
             deposited_amount: amount_to_lock,
-            vault: ctx.accounts.vault.key(),
-            vault_bump: args.vault_bump,
+            withdrawn_amount: 0,
+            beneficiary: args.beneficiary,
+            start_emission: args.start_emission,
+            cliff: args.cliff,
+            schedule: args.schedule,
+            receipt_mint: receipt_accounts.map(|(receipt_mint, _)| receipt_mint.key()),
+            beneficiaries: args
+                .beneficiaries
+                .iter()
+                .map(|share| Beneficiary {
+                    owner: share.owner,
+                    share_bps: share.bps,
+                    withdrawn_amount: 0,
+                })
+                .collect(),
+            delegate: None,
+            delegate_permissions: 0,
+            vested_at_relock: 0,
+            reserved: [0; 56],
         };
+        mint_info.add_locker(locker)?;
 
         TokenTransfer {
             amount: amount_to_lock,
-            from: &mut ctx.accounts.funding_wallet,
+            from: &ctx.accounts.funding_wallet,
             to: &ctx.accounts.vault,
+            mint: &ctx.accounts.mint,
+            decimals: mint.decimals,
             authority: &ctx.accounts.funding_wallet_authority,
             token_program: &ctx.accounts.token_program,
             signers: None,
         }
         .make()?;
 
+        if let Some((receipt_mint, receipt_account)) = receipt_accounts {
+            let seeds = &[locker_key.as_ref(), &[args.vault_bump]];
+
+            Receipt {
+                mint: receipt_mint,
+                account: receipt_account,
+                owner: &locker.owner,
+                vault_authority: &ctx.accounts.vault_authority,
+                token_program: &ctx.accounts.token_program,
+                signers: &[&seeds[..]],
+            }
+            .issue()?;
+        }
+
+        emit!(LockerCreated {
+            locker: locker_key,
+            owner: locker.owner,
+            creator: ctx.accounts.creator.key(),
+            mint: ctx.accounts.mint.key(),
+            vault: locker.vault,
+            country_code: locker.country_code,
+            amount: args.amount,
+            fee: lock_fee,
+            deposited_amount: locker.deposited_amount,
+            unlock_date: locker.current_unlock_date,
+            start_emission: locker.start_emission,
+            cliff: locker.cliff,
+            schedule: locker.schedule.clone(),
+            receipt_mint: locker.receipt_mint,
+            beneficiary: locker.beneficiary,
+            beneficiaries: locker.beneficiaries.clone(),
+            revocable: locker.revocable,
+        });
+
         Ok(())
     }
 
     pub fn relock(ctx: Context<Relock>, unlock_date: i64) -> Result<()> {
         let locker = &mut ctx.accounts.locker;
+        let signer = ctx.accounts.owner.key();
+        if !locker.is_delegate(&signer, Locker::PERMISSION_RELOCK) {
+            locker.require_receipt_holder(&signer, ctx.remaining_accounts)?;
+        }
 
-        require!(
-            unlock_date > locker.current_unlock_date,
-            CannotUnlockToEarlierDate
-        );
+        let old_unlock_date = locker.current_unlock_date;
+        let mint_info = &mut ctx.accounts.mint_info;
+        mint_info.remove_locker(locker);
+        locker.relock(unlock_date, ctx.accounts.clock.unix_timestamp)?;
+        mint_info.add_locker(locker)?;
 
-        locker.current_unlock_date = unlock_date;
+        emit!(Relocked {
+            locker: locker.key(),
+            old_unlock_date,
+            new_unlock_date: unlock_date,
+            vested_amount: locker.vested_at_relock,
+        });
 
         Ok(())
     }
 
+    /// For lockers with beneficiaries the signer hands over only its share,
+    /// the owner managing such locker can't be changed.
     pub fn transfer_ownership(ctx: Context<TransferOwnership>) -> Result<()> {
+        require!(!ctx.accounts.config.paused, Paused);
+
+        let locker = &mut ctx.accounts.locker;
+        require!(locker.receipt_mint.is_none(), LockerHasReceipt);
+
+        let old_owner = ctx.accounts.owner.key();
+        let new_owner = ctx.accounts.new_owner.key();
+
+        if locker.beneficiaries.is_empty() {
+            locker.owner = new_owner;
+            // delegate was trusted by the old owner only
+            locker.delegate = None;
+            locker.delegate_permissions = 0;
+        } else {
+            // every beneficiary hands over only its own share
+            require!(
+                locker.beneficiaries.iter().all(|b| b.owner != new_owner),
+                InvalidBeneficiaries
+            );
+            let beneficiary = locker
+                .beneficiaries
+                .iter_mut()
+                .find(|b| b.owner == old_owner)
+                .ok_or(ErrorCode::NotBeneficiary)?;
+            beneficiary.owner = new_owner;
+        }
+
+        emit!(OwnershipTransferred {
+            locker: locker.key(),
+            old_owner,
+            new_owner,
+        });
+
+        Ok(())
+    }
+
+    pub fn set_beneficiary(
+        ctx: Context<SetBeneficiary>,
+        beneficiary: Option<Pubkey>,
+    ) -> Result<()> {
+        require!(!ctx.accounts.config.paused, Paused);
+
+        let locker = &mut ctx.accounts.locker;
+        locker.require_receipt_holder(&ctx.accounts.owner.key(), ctx.remaining_accounts)?;
+        require!(locker.beneficiaries.is_empty(), LockerHasBeneficiaries);
+
+        let old_beneficiary = locker.beneficiary;
+        locker.beneficiary = beneficiary;
+
+        emit!(BeneficiaryChanged {
+            locker: locker.key(),
+            old_beneficiary,
+            new_beneficiary: beneficiary,
+        });
+
+        Ok(())
+    }
+
+    pub fn set_delegate(
+        ctx: Context<SetDelegate>,
+        delegate: Option<Pubkey>,
+        permissions: u8,
+    ) -> Result<()> {
+        require!(!ctx.accounts.config.paused, Paused);
+
         let locker = &mut ctx.accounts.locker;
+        // receipt lockers change hands without the program knowing
+        require!(locker.receipt_mint.is_none(), LockerHasReceipt);
+        require!(permissions & !Locker::PERMISSION_ALL == 0, InvalidDelegate);
+        require!(delegate.is_some() || permissions == 0, InvalidDelegate);
+        // beneficiaries withdraw their shares themselves
+        if !locker.beneficiaries.is_empty() {
+            require!(
+                permissions & Locker::PERMISSION_WITHDRAW == 0,
+                LockerHasBeneficiaries
+            );
+        }
 
-        locker.owner = ctx.accounts.new_owner.key();
+        locker.delegate = delegate;
+        locker.delegate_permissions = permissions;
+
+        emit!(DelegateChanged {
+            locker: locker.key(),
+            delegate,
+            permissions,
+        });
 
         Ok(())
     }
 
-    pub fn increment_lock(ctx: Context<IncrementLock>, amount: u64) -> Result<()> {
+    pub fn increment_lock<'info>(
+        ctx: Context<'_, '_, '_, 'info, IncrementLock<'info>>,
+        amount: u64,
+        referrer: Option<Pubkey>,
+    ) -> Result<()> {
+        let now = ctx.accounts.clock.unix_timestamp;
         let locker = &mut ctx.accounts.locker;
-        let mint_info = &ctx.accounts.mint_info;
+        let mint_info = &mut ctx.accounts.mint_info;
         let config = &ctx.accounts.config;
+        require!(!config.paused, Paused);
+        // added tokens would become revocable by the creator
+        require!(!locker.revocable, CannotIncrementRevocable);
 
-        // 3rd argument is false b/c we do not pay in sol here at all
-        let amount_to_lock = if should_pay_in_tokens(config, mint_info, false) {
-            let lock_fee = mul_div(
-                amount,
-                config.fee_in_token_numerator,
-                config.fee_in_token_denominator,
-            )
-            .ok_or(ErrorCode::IntegerOverflow)?;
+        let signer = ctx.accounts.funding_wallet_authority.key();
+        if !locker.is_delegate(&signer, Locker::PERMISSION_INCREMENT) {
+            locker.require_receipt_holder(&signer, ctx.remaining_accounts)?;
+        }
+        validate_referrer(
+            referrer,
+            &[signer, locker.owner, locker.creator],
+            ctx.remaining_accounts,
+        )?;
 
+        let mint = token_interface::Mint::load(&ctx.accounts.mint)?;
+        mint.require_lockable()?;
+
+        // 3rd argument is false b/c we do not pay in sol here at all
+        let lock_fee = if should_pay_in_tokens(config, mint_info, false) {
             FeeInTokens {
                 config,
-                funding_wallet: &mut ctx.accounts.funding_wallet,
+                funding_wallet: &ctx.accounts.funding_wallet,
                 funding_wallet_authority: &ctx.accounts.funding_wallet_authority,
                 fee_wallet: &ctx.accounts.fee_wallet,
-                amount: lock_fee,
+                referrer: referrer
+                    .map(|referrer| {
+                        remaining_token_account(
+                            ctx.remaining_accounts,
+                            &referrer,
+                            &ctx.accounts.mint,
+                            &ctx.accounts.token_program,
+                            ErrorCode::InvalidReferrer,
+                        )
+                    })
+                    .transpose()?,
+                remaining_accounts: ctx.remaining_accounts,
+                mint: &ctx.accounts.mint,
+                decimals: mint.decimals,
+                amount,
+                lock_duration: locker.current_unlock_date - now,
+                // exemption belongs to the project that created the locker,
+                // whoever tops it up
+                discount_bps: fee_discount(ctx.remaining_accounts, &locker.creator),
                 token_program: &ctx.accounts.token_program,
             }
-            .pay()?;
-
-            amount - lock_fee
+            .pay()?
         } else {
-            amount
+            0
         };
 
+        let amount_to_lock = amount
+            .checked_sub(lock_fee)
+            .ok_or(ErrorCode::IntegerOverflow)?;
+
         TokenTransfer {
             amount: amount_to_lock,
-            from: &mut ctx.accounts.funding_wallet,
+            from: &ctx.accounts.funding_wallet,
             to: &ctx.accounts.vault,
+            mint: &ctx.accounts.mint,
+            decimals: mint.decimals,
             authority: &ctx.accounts.funding_wallet_authority,
             token_program: &ctx.accounts.token_program,
             signers: None,
         }
         .make()?;
 
+        // new tokens follow the same schedule, so their vested part
+        // becomes available right away while already withdrawn amount stays
+        mint_info.remove_locker(locker);
         locker.deposited_amount = locker
             .deposited_amount
             .checked_add(amount_to_lock)
             .ok_or(ErrorCode::IntegerOverflow)?;
+        mint_info.add_locker(locker)?;
+
+        emit!(LockIncremented {
+            locker: locker.key(),
+            amount,
+            fee: lock_fee,
+            deposited_amount: locker.deposited_amount,
+        });
 
         Ok(())
     }
 
     pub fn withdraw_funds(ctx: Context<WithdrawFunds>, amount: u64) -> Result<()> {
+        let config = &ctx.accounts.config;
+        require!(
+            !config.paused || config.allow_withdrawals_when_paused,
+            Paused
+        );
+
         let now = ctx.accounts.clock.unix_timestamp;
-        let locker = &ctx.accounts.locker;
-        let vault = &mut ctx.accounts.vault;
+        let locker = &mut ctx.accounts.locker;
+        let owner = ctx.accounts.owner.key();
+        let is_delegate = locker.is_delegate(&owner, Locker::PERMISSION_WITHDRAW);
+
+        let share_index = if is_delegate {
+            None
+        } else if locker.beneficiaries.is_empty() {
+            locker.require_receipt_holder(&owner, ctx.remaining_accounts)?;
+            None
+        } else {
+            let index = locker
+                .beneficiaries
+                .iter()
+                .position(|b| b.owner == owner)
+                .ok_or(ErrorCode::NotBeneficiary)?;
+            Some(index)
+        };
 
-        let amount_to_transfer = match locker.start_emission {
-            Some(start_emission) => {
-                let clamped_time = now.clamp(start_emission, locker.current_unlock_date);
-                let elapsed = clamped_time - start_emission;
-                let full_period = locker.current_unlock_date - start_emission;
-                require!(full_period > 0, InvalidPeriod);
+        let mint = token_interface::Mint::load(&ctx.accounts.mint)?;
 
-                sol_log_64(
-                    amount,
-                    elapsed as u64,
-                    full_period as u64,
-                    now as u64,
-                    start_emission as u64,
-                );
+        let vault = token_interface::TokenAccount::load(&ctx.accounts.vault)?;
+        require!(
+            vault.owner == ctx.accounts.vault_authority.key(),
+            InvalidTokenAccount
+        );
 
-                mul_div(locker.deposited_amount, elapsed, full_period as u64)
-                    .ok_or(ErrorCode::IntegerOverflow)?
-                    .min(amount)
-            }
-            None => {
+        // beneficiaries with shares withdraw to themselves,
+        // delegate withdraws only to where the owner would
+        let recipient = match share_index {
+            Some(_) => owner,
+            None if is_delegate => locker.beneficiary.unwrap_or(locker.owner),
+            None => locker.beneficiary.unwrap_or(owner),
+        };
+        let target_wallet = token_interface::TokenAccount::load(&ctx.accounts.target_wallet)?;
+        require!(target_wallet.owner == recipient, InvalidTargetWallet);
+
+        let amount_to_transfer = if let Some(index) = share_index {
+            if !locker.has_emission() {
                 require!(now > locker.current_unlock_date, TooEarlyToWithdraw);
-                amount.min(vault.amount)
             }
+            locker.beneficiary_available_amount(index, now)?.min(amount)
+        } else if locker.has_emission() {
+            // vested amount may lag behind by a token after a split
+            locker
+                .vested_amount(now)?
+                .saturating_sub(locker.withdrawn_amount)
+                .min(amount)
+        } else {
+            require!(now > locker.current_unlock_date, TooEarlyToWithdraw);
+            amount.min(vault.amount)
         };
 
         require!(amount_to_transfer > 0, InvalidAmount);
@@ -293,146 +889,937 @@ pub mod locker {
 
         TokenTransfer {
             amount: amount_to_transfer,
-            from: vault,
+            from: &ctx.accounts.vault,
             to: &ctx.accounts.target_wallet,
+            mint: &ctx.accounts.mint,
+            decimals: mint.decimals,
             authority: &ctx.accounts.vault_authority,
             token_program: &ctx.accounts.token_program,
             signers: Some(signers),
         }
         .make()?;
 
-        vault.reload()?;
+        let mint_info = &mut ctx.accounts.mint_info;
+        mint_info.remove_locker(locker);
+        locker.withdrawn_amount = locker
+            .withdrawn_amount
+            .checked_add(amount_to_transfer)
+            .ok_or(ErrorCode::IntegerOverflow)?;
+
+        if let Some(index) = share_index {
+            let beneficiary = &mut locker.beneficiaries[index];
+            beneficiary.withdrawn_amount = beneficiary
+                .withdrawn_amount
+                .checked_add(amount_to_transfer)
+                .ok_or(ErrorCode::IntegerOverflow)?;
+        }
+
+        emit!(FundsWithdrawn {
+            locker: locker_key,
+            owner: ctx.accounts.owner.key(),
+            target_wallet: ctx.accounts.target_wallet.key(),
+            amount: amount_to_transfer,
+            withdrawn_amount: locker.withdrawn_amount,
+        });
+
+        let vault = token_interface::TokenAccount::load(&ctx.accounts.vault)?;
         if vault.amount == 0 {
-            let cpi_ctx = CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                CloseAccount {
-                    account: vault.to_account_info(),
-                    destination: ctx.accounts.owner.to_account_info(),
-                    authority: ctx.accounts.vault_authority.to_account_info(),
-                },
+            // rent goes to the owner even if a delegate or beneficiary signs
+            token_interface::close_account(
+                &ctx.accounts.token_program,
+                &ctx.accounts.vault,
+                &ctx.accounts.locker_owner,
+                &ctx.accounts.vault_authority,
                 signers,
-            );
-            token::close_account(cpi_ctx)?;
+            )?;
 
-            locker.close(ctx.accounts.owner.to_account_info())?;
+            locker.close(ctx.accounts.locker_owner.to_account_info())?;
+
+            emit!(LockerClosed {
+                locker: locker_key,
+                owner: locker.owner,
+            });
+        } else {
+            mint_info.add_locker(locker)?;
         }
 
         Ok(())
     }
 
     pub fn split_locker(ctx: Context<SplitLocker>, args: SplitLockerArgs) -> Result<()> {
+        require!(!ctx.accounts.config.paused, Paused);
         require!(args.amount > 0, InvalidAmount);
 
         let new_locker = ctx.accounts.new_locker.deref_mut();
         let old_locker = &mut ctx.accounts.old_locker;
-        let old_vault = &mut ctx.accounts.old_vault;
+        let signer = ctx.accounts.old_owner.key();
+        if old_locker.is_delegate(&signer, Locker::PERMISSION_SPLIT) {
+            // delegate may only rearrange tokens of the owner
+            require!(
+                ctx.accounts.new_owner.key() == old_locker.owner,
+                InvalidDelegate
+            );
+            if args.unlock_date.is_some() {
+                require!(
+                    old_locker.is_delegate(&signer, Locker::PERMISSION_RELOCK),
+                    InvalidDelegate
+                );
+            }
+        } else {
+            old_locker.require_receipt_holder(&signer, ctx.remaining_accounts)?;
+        }
+        let mint = token_interface::Mint::load(&ctx.accounts.mint)?;
+
+        let old_vault = token_interface::TokenAccount::load(&ctx.accounts.old_vault)?;
+        require!(
+            old_vault.owner == ctx.accounts.old_vault_authority.key(),
+            InvalidTokenAccount
+        );
+        let new_vault = token_interface::TokenAccount::load(&ctx.accounts.new_vault)?;
+        require!(
+            new_vault.owner == ctx.accounts.new_vault_authority.key(),
+            InvalidTokenAccount
+        );
 
         require!(args.amount <= old_vault.amount, InvalidAmount);
 
+        let mint_info = &mut ctx.accounts.mint_info;
+        mint_info.remove_locker(old_locker);
+        let (deposited_amount, withdrawn_amount, vested_at_relock) =
+            old_locker.split_off(args.amount)?;
+
         let locker_key = old_locker.key();
         let seeds = &[locker_key.as_ref(), &[old_locker.vault_bump]];
         let signers = &[&seeds[..]];
 
         TokenTransfer {
             amount: args.amount,
-            from: old_vault,
+            from: &ctx.accounts.old_vault,
             to: &ctx.accounts.new_vault,
+            mint: &ctx.accounts.mint,
+            decimals: mint.decimals,
             authority: &ctx.accounts.old_vault_authority,
             token_program: &ctx.accounts.token_program,
             signers: Some(signers),
         }
         .make()?;
 
-        old_locker.deposited_amount = old_locker
-            .deposited_amount
-            .checked_sub(args.amount)
-            .ok_or(ErrorCode::IntegerOverflow)?;
-
-        old_vault.reload()?;
+        let old_vault = token_interface::TokenAccount::load(&ctx.accounts.old_vault)?;
         if old_vault.amount == 0 {
-            let cpi_ctx = CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                CloseAccount {
-                    account: old_vault.to_account_info(),
-                    destination: ctx.accounts.old_owner.to_account_info(),
-                    authority: ctx.accounts.old_vault_authority.to_account_info(),
-                },
+            // rent goes to the owner even if a delegate signs
+            token_interface::close_account(
+                &ctx.accounts.token_program,
+                &ctx.accounts.old_vault,
+                &ctx.accounts.locker_owner,
+                &ctx.accounts.old_vault_authority,
                 signers,
-            );
-            token::close_account(cpi_ctx)?;
+            )?;
+
+            old_locker.close(ctx.accounts.locker_owner.to_account_info())?;
 
-            old_locker.close(ctx.accounts.old_owner.to_account_info())?;
+            emit!(LockerClosed {
+                locker: locker_key,
+                owner: old_locker.owner,
+            });
+        } else {
+            mint_info.add_locker(old_locker)?;
         }
 
         *new_locker = Locker {
+            version: Locker::VERSION,
             owner: ctx.accounts.new_owner.key(),
+            // split off part can still be revoked
+            creator: old_locker.creator,
+            mint: old_locker.mint,
+            vault: ctx.accounts.new_vault.key(),
+            vault_bump: args.vault_bump,
+            revocable: old_locker.revocable,
             country_code: old_locker.country_code,
             current_unlock_date: old_locker.current_unlock_date,
+            deposited_amount,
+            withdrawn_amount,
+            beneficiary: None,
             start_emission: old_locker.start_emission,
-            deposited_amount: args.amount,
-            vault: ctx.accounts.new_vault.key(),
-            vault_bump: args.vault_bump,
+            cliff: old_locker.cliff,
+            // tranches are relative to deposited amount, so they are copied as is
+            schedule: old_locker.schedule.clone(),
+            // split off part is owned directly by the new owner
+            receipt_mint: None,
+            beneficiaries: Vec::new(),
+            delegate: None,
+            delegate_permissions: 0,
+            vested_at_relock,
+            reserved: [0; 56],
         };
+        if let Some(unlock_date) = args.unlock_date {
+            new_locker.relock(unlock_date, ctx.accounts.clock.unix_timestamp)?;
+        }
+        mint_info.add_locker(new_locker)?;
+        let new_unlock_date = new_locker.current_unlock_date;
+        let new_vested_at_relock = new_locker.vested_at_relock;
+
+        emit!(LockerSplit {
+            old_locker: locker_key,
+            new_locker: ctx.accounts.new_locker.key(),
+            new_owner: ctx.accounts.new_owner.key(),
+            amount: args.amount,
+            old_deposited_amount: old_locker.deposited_amount,
+            new_deposited_amount: deposited_amount,
+        });
+
+        if args.unlock_date.is_some() {
+            emit!(Relocked {
+                locker: ctx.accounts.new_locker.key(),
+                old_unlock_date: old_locker.current_unlock_date,
+                new_unlock_date,
+                vested_amount: new_vested_at_relock,
+            });
+        }
 
         Ok(())
     }
 
-    pub fn close_locker(ctx: Context<CloseLocker>) -> Result<()> {
-        let locker = &ctx.accounts.locker;
-        let vault = &mut ctx.accounts.vault;
+    pub fn merge_lockers(ctx: Context<MergeLockers>) -> Result<()> {
+        require!(!ctx.accounts.config.paused, Paused);
 
-        let locker_key = locker.key();
-        let seeds = &[locker_key.as_ref(), &[locker.vault_bump]];
+        let owner = ctx.accounts.owner.key();
+        let source_locker = &mut ctx.accounts.source_locker;
+        let destination_locker = &mut ctx.accounts.destination_locker;
+
+        require!(
+            source_locker.key() != destination_locker.key(),
+            InvalidMerge
+        );
+        source_locker.require_receipt_holder(&owner, ctx.remaining_accounts)?;
+        destination_locker.require_receipt_holder(&owner, ctx.remaining_accounts)?;
+
+        let mint_info = &mut ctx.accounts.mint_info;
+        mint_info.remove_locker(source_locker);
+        mint_info.remove_locker(destination_locker);
+        destination_locker.merge(source_locker)?;
+        mint_info.add_locker(destination_locker)?;
+
+        let mint = token_interface::Mint::load(&ctx.accounts.mint)?;
+        let source_vault = token_interface::TokenAccount::load(&ctx.accounts.source_vault)?;
+
+        let locker_key = source_locker.key();
+        let seeds = &[locker_key.as_ref(), &[source_locker.vault_bump]];
         let signers = &[&seeds[..]];
 
         TokenTransfer {
-            amount: vault.amount,
-            from: vault,
-            to: &ctx.accounts.target_wallet,
-            authority: &ctx.accounts.vault_authority,
+            amount: source_vault.amount,
+            from: &ctx.accounts.source_vault,
+            to: &ctx.accounts.destination_vault,
+            mint: &ctx.accounts.mint,
+            decimals: mint.decimals,
+            authority: &ctx.accounts.source_vault_authority,
             token_program: &ctx.accounts.token_program,
             signers: Some(signers),
         }
         .make()?;
 
-        let cpi_ctx = CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(),
-            CloseAccount {
-                account: vault.to_account_info(),
-                destination: ctx.accounts.owner.to_account_info(),
-                authority: ctx.accounts.vault_authority.to_account_info(),
-            },
+        token_interface::close_account(
+            &ctx.accounts.token_program,
+            &ctx.accounts.source_vault,
+            &ctx.accounts.owner,
+            &ctx.accounts.source_vault_authority,
             signers,
-        );
-        token::close_account(cpi_ctx)?;
+        )?;
 
-        locker.close(ctx.accounts.owner.to_account_info())?;
+        source_locker.close(ctx.accounts.owner.to_account_info())?;
+
+        emit!(LockersMerged {
+            source_locker: locker_key,
+            destination_locker: destination_locker.key(),
+            amount: source_vault.amount,
+            deposited_amount: destination_locker.deposited_amount,
+            unlock_date: destination_locker.current_unlock_date,
+        });
+
+        emit!(LockerClosed {
+            locker: locker_key,
+            owner,
+        });
 
         Ok(())
     }
-}
 
-#[account]
-#[derive(Debug)]
-pub struct Config {
-    admin: Pubkey,
-    fee_in_sol: u64,
-    fee_in_token_numerator: u64,
-    fee_in_token_denominator: u64,
-    mint_info_permissioned: bool,
-    has_linear_emission: bool,
-    fee_wallet: Pubkey,
-    country_list: Pubkey,
-    bump: u8,
-}
+    pub fn revoke(ctx: Context<Revoke>) -> Result<()> {
+        require!(!ctx.accounts.config.paused, Paused);
 
-impl Config {
-    pub const LEN: usize = 8 + std::mem::size_of::<Self>();
+        let now = ctx.accounts.clock.unix_timestamp;
+        let locker = &mut ctx.accounts.locker;
+        let mint = token_interface::Mint::load(&ctx.accounts.mint)?;
+
+        let target_wallet = token_interface::TokenAccount::load(&ctx.accounts.target_wallet)?;
+        require!(target_wallet.owner == locker.creator, InvalidTargetWallet);
+
+        let mint_info = &mut ctx.accounts.mint_info;
+        mint_info.remove_locker(locker);
+        let unvested_amount = locker.revoke(now)?;
+
+        let locker_key = locker.key();
+        let seeds = &[locker_key.as_ref(), &[locker.vault_bump]];
+        let signers = &[&seeds[..]];
+
+        TokenTransfer {
+            amount: unvested_amount,
+            from: &ctx.accounts.vault,
+            to: &ctx.accounts.target_wallet,
+            mint: &ctx.accounts.mint,
+            decimals: mint.decimals,
+            authority: &ctx.accounts.vault_authority,
+            token_program: &ctx.accounts.token_program,
+            signers: Some(signers),
+        }
+        .make()?;
+
+        emit!(Revoked {
+            locker: locker_key,
+            creator: locker.creator,
+            amount: unvested_amount,
+            deposited_amount: locker.deposited_amount,
+        });
+
+        // nothing was vested or everything is withdrawn already
+        let vault = token_interface::TokenAccount::load(&ctx.accounts.vault)?;
+        if vault.amount == 0 {
+            token_interface::close_account(
+                &ctx.accounts.token_program,
+                &ctx.accounts.vault,
+                &ctx.accounts.creator,
+                &ctx.accounts.vault_authority,
+                signers,
+            )?;
+
+            locker.close(ctx.accounts.creator.to_account_info())?;
+
+            emit!(LockerClosed {
+                locker: locker_key,
+                owner: locker.owner,
+            });
+        } else {
+            mint_info.add_locker(locker)?;
+        }
+
+        Ok(())
+    }
+
+    /// For test purposes only! Built with `test-instructions` feature,
+    /// fails otherwise, see `emergency_unlock` for the production way.
+    pub fn close_locker(ctx: Context<CloseLocker>) -> Result<()> {
+        test_instructions::close_locker(ctx)
+    }
+
+    /// Owner asks admin to unlock the locker before its unlock date.
+    pub fn request_emergency_unlock(ctx: Context<RequestEmergencyUnlock>, bump: u8) -> Result<()> {
+        require!(!ctx.accounts.config.paused, Paused);
+
+        let now = ctx.accounts.clock.unix_timestamp;
+        let locker = &ctx.accounts.locker;
+        let owner = ctx.accounts.owner.key();
+        locker.require_receipt_holder(&owner, ctx.remaining_accounts)?;
+        // shares of beneficiaries are not the owner's to take
+        require!(locker.beneficiaries.is_empty(), LockerHasBeneficiaries);
+        require!(now < locker.current_unlock_date, AlreadyUnlocked);
+
+        let request = &mut ctx.accounts.emergency_unlock;
+        request.locker = locker.key();
+        request.requester = owner;
+        request.requested_at = now;
+        request.approved_at = None;
+        request.bump = bump;
+
+        emit!(EmergencyUnlockRequested {
+            locker: request.locker,
+            requester: owner,
+        });
+
+        Ok(())
+    }
+
+    pub fn approve_emergency_unlock(ctx: Context<ApproveEmergencyUnlock>) -> Result<()> {
+        let request = &mut ctx.accounts.emergency_unlock;
+        require!(
+            request.approved_at.is_none(),
+            EmergencyUnlockAlreadyApproved
+        );
+
+        let now = ctx.accounts.clock.unix_timestamp;
+        request.approved_at = Some(now);
+
+        emit!(EmergencyUnlockApproved {
+            locker: request.locker,
+            unlock_after: now
+                .checked_add(EmergencyUnlock::DELAY)
+                .ok_or(ErrorCode::IntegerOverflow)?,
+        });
+
+        Ok(())
+    }
+
+    /// Drops the request, either by the requester, the current owner
+    /// of the locker or by admin.
+    pub fn cancel_emergency_unlock(ctx: Context<CancelEmergencyUnlock>) -> Result<()> {
+        let request = &ctx.accounts.emergency_unlock;
+        request.close(ctx.accounts.requester.to_account_info())?;
+
+        emit!(EmergencyUnlockCancelled {
+            locker: request.locker,
+            cancelled_by: ctx.accounts.authority.key(),
+        });
+
+        Ok(())
+    }
+
+    /// Withdraws everything from the locker regardless of its unlock date
+    /// once the request is approved and the delay has passed, then closes
+    /// the locker.
+    pub fn emergency_unlock(ctx: Context<EmergencyUnlockLocker>) -> Result<()> {
+        let config = &ctx.accounts.config;
+        require!(
+            !config.paused || config.allow_withdrawals_when_paused,
+            Paused
+        );
+
+        let now = ctx.accounts.clock.unix_timestamp;
+        let request = &ctx.accounts.emergency_unlock;
+        let unlock_after = request
+            .approved_at
+            .ok_or(ErrorCode::EmergencyUnlockNotReady)?
+            .checked_add(EmergencyUnlock::DELAY)
+            .ok_or(ErrorCode::IntegerOverflow)?;
+        require!(now >= unlock_after, EmergencyUnlockNotReady);
+
+        let locker = &ctx.accounts.locker;
+        let owner = ctx.accounts.owner.key();
+        locker.require_receipt_holder(&owner, ctx.remaining_accounts)?;
+        require!(locker.beneficiaries.is_empty(), LockerHasBeneficiaries);
+        // nothing to unlock early once the unlock date has passed
+        require!(now < locker.current_unlock_date, AlreadyUnlocked);
+
+        let mint = token_interface::Mint::load(&ctx.accounts.mint)?;
+
+        let vault = token_interface::TokenAccount::load(&ctx.accounts.vault)?;
+        require!(
+            vault.owner == ctx.accounts.vault_authority.key(),
+            InvalidTokenAccount
+        );
+
+        let target_wallet = token_interface::TokenAccount::load(&ctx.accounts.target_wallet)?;
+        require!(
+            target_wallet.owner == locker.beneficiary.unwrap_or(owner),
+            InvalidTargetWallet
+        );
+
+        let locker_key = locker.key();
+        let seeds = &[locker_key.as_ref(), &[locker.vault_bump]];
+        let signers = &[&seeds[..]];
+
+        TokenTransfer {
+            amount: vault.amount,
+            from: &ctx.accounts.vault,
+            to: &ctx.accounts.target_wallet,
+            mint: &ctx.accounts.mint,
+            decimals: mint.decimals,
+            authority: &ctx.accounts.vault_authority,
+            token_program: &ctx.accounts.token_program,
+            signers: Some(signers),
+        }
+        .make()?;
+
+        token_interface::close_account(
+            &ctx.accounts.token_program,
+            &ctx.accounts.vault,
+            &ctx.accounts.owner,
+            &ctx.accounts.vault_authority,
+            signers,
+        )?;
+
+        ctx.accounts.mint_info.remove_locker(locker);
+        locker.close(ctx.accounts.owner.to_account_info())?;
+        request.close(ctx.accounts.owner.to_account_info())?;
+
+        emit!(EmergencyUnlocked {
+            locker: locker_key,
+            owner,
+            amount: vault.amount,
+        });
+
+        Ok(())
+    }
+
+    /// Upgrades config created before accounts had a version.
+    pub fn migrate_config(ctx: Context<MigrateConfig>) -> Result<()> {
+        let legacy: legacy::Config = legacy::load::<Config, _>(&ctx.accounts.config)?;
+        require!(legacy.admin == ctx.accounts.admin.key(), InvalidMigration);
+
+        let config = Config {
+            version: Config::VERSION,
+            admin: legacy.admin,
+            pending_admin: None,
+            fee_in_lamports: legacy
+                .fee_in_sol
+                .checked_mul(solana_program::native_token::LAMPORTS_PER_SOL)
+                .ok_or(ErrorCode::IntegerOverflow)?,
+            fee_in_token_numerator: legacy.fee_in_token_numerator,
+            fee_in_token_denominator: legacy.fee_in_token_denominator,
+            fee_in_stable: 0,
+            stable_fee_mint: None,
+            fee_in_usd_cents: 0,
+            sol_price_feed: None,
+            fee_tiers: Vec::new(),
+            referral_bps: 0,
+            fee_recipients: Vec::new(),
+            mint_info_permissioned: legacy.mint_info_permissioned,
+            has_linear_emission: legacy.has_linear_emission,
+            fee_wallet: legacy.fee_wallet,
+            country_list: legacy.country_list,
+            paused: false,
+            allow_withdrawals_when_paused: true,
+            bump: legacy.bump,
+            reserved: [0; 64],
+        };
+        write_migrated(
+            &ctx.accounts.config,
+            &config,
+            Config::LEN,
+            &ctx.accounts.admin,
+            &ctx.accounts.system_program,
+        )?;
+
+        emit!(AccountMigrated {
+            account: ctx.accounts.config.key(),
+            version: Config::VERSION,
+        });
+        emit!(config.to_event());
+
+        Ok(())
+    }
+
+    /// Upgrades mint info created before accounts had a version, it should
+    /// be done before upgrading lockers of the mint.
+    pub fn migrate_mint_info(ctx: Context<MigrateMintInfo>) -> Result<()> {
+        let legacy: legacy::MintInfo = legacy::load::<MintInfo, _>(&ctx.accounts.mint_info)?;
+        let address = Pubkey::create_program_address(
+            &[ctx.accounts.mint.key.as_ref(), &[legacy.bump]],
+            ctx.program_id,
+        )
+        .map_err(|_| ErrorCode::InvalidMigration)?;
+        require!(address == ctx.accounts.mint_info.key(), InvalidMigration);
+
+        if ctx.accounts.config.mint_info_permissioned {
+            require!(
+                ctx.accounts.payer.key() == ctx.accounts.config.admin,
+                InitMintInfoNotAuthorized
+            );
+        }
+
+        // lockers are added to stats as they are upgraded
+        let mint_info = MintInfo {
+            bump: legacy.bump,
+            fee_paid: legacy.fee_paid,
+            ..Default::default()
+        };
+        write_migrated(
+            &ctx.accounts.mint_info,
+            &mint_info,
+            MintInfo::LEN,
+            &ctx.accounts.payer,
+            &ctx.accounts.system_program,
+        )?;
+
+        emit!(AccountMigrated {
+            account: ctx.accounts.mint_info.key(),
+            version: MintInfo::VERSION,
+        });
+
+        Ok(())
+    }
+
+    /// Upgrades locker created before accounts had a version. Creator of such
+    /// lockers is unknown, so the owner becomes the creator.
+    pub fn migrate_locker(ctx: Context<MigrateLocker>) -> Result<()> {
+        let legacy: legacy::Locker = legacy::load::<Locker, _>(&ctx.accounts.locker)?;
+        require!(legacy.owner == ctx.accounts.owner.key(), InvalidMigration);
+        require!(legacy.vault == ctx.accounts.vault.key(), InvalidMigration);
+
+        let vault = token_interface::TokenAccount::load(&ctx.accounts.vault)?;
+        let vault_authority = Pubkey::create_program_address(
+            &[ctx.accounts.locker.key.as_ref(), &[legacy.vault_bump]],
+            ctx.program_id,
+        )
+        .map_err(|_| ErrorCode::InvalidMigration)?;
+        require!(
+            vault.owner == vault_authority && vault.mint == ctx.accounts.mint.key(),
+            InvalidMigration
+        );
+
+        let locker = Locker {
+            version: Locker::VERSION,
+            owner: legacy.owner,
+            creator: legacy.owner,
+            mint: vault.mint,
+            vault: legacy.vault,
+            vault_bump: legacy.vault_bump,
+            revocable: false,
+            country_code: legacy.country_code,
+            current_unlock_date: legacy.current_unlock_date,
+            deposited_amount: legacy.deposited_amount,
+            withdrawn_amount: legacy.deposited_amount.saturating_sub(vault.amount),
+            beneficiary: None,
+            start_emission: legacy.start_emission,
+            cliff: None,
+            schedule: Vec::new(),
+            receipt_mint: None,
+            beneficiaries: Vec::new(),
+            delegate: None,
+            delegate_permissions: 0,
+            vested_at_relock: 0,
+            reserved: [0; 56],
+        };
+        write_migrated(
+            &ctx.accounts.locker,
+            &locker,
+            Locker::space(0, 0),
+            &ctx.accounts.owner,
+            &ctx.accounts.system_program,
+        )?;
+        ctx.accounts.mint_info.add_locker(&locker)?;
+
+        emit!(AccountMigrated {
+            account: ctx.accounts.locker.key(),
+            version: Locker::VERSION,
+        });
+
+        Ok(())
+    }
+}
+
+#[event]
+pub struct ConfigUpdated {
+    pub admin: Pubkey,
+    pub fee_in_lamports: u64,
+    pub fee_in_token_numerator: u64,
+    pub fee_in_token_denominator: u64,
+    pub fee_in_stable: u64,
+    pub stable_fee_mint: Option<Pubkey>,
+    pub fee_in_usd_cents: u64,
+    pub sol_price_feed: Option<PriceFeed>,
+    pub fee_tiers: Vec<FeeTier>,
+    pub referral_bps: u16,
+    pub fee_recipients: Vec<FeeRecipient>,
+    pub mint_info_permissioned: bool,
+    pub has_linear_emission: bool,
+    pub fee_wallet: Pubkey,
+    pub country_list: Pubkey,
+    pub paused: bool,
+    pub allow_withdrawals_when_paused: bool,
+}
+
+#[event]
+pub struct LockerCreated {
+    pub locker: Pubkey,
+    pub owner: Pubkey,
+    pub creator: Pubkey,
+    pub mint: Pubkey,
+    pub vault: Pubkey,
+    pub country_code: [u8; 2],
+    /// Amount taken from funding wallet including the fee.
+    pub amount: u64,
+    pub fee: u64,
+    pub deposited_amount: u64,
+    pub unlock_date: i64,
+    pub start_emission: Option<i64>,
+    pub cliff: Option<Cliff>,
+    pub schedule: Vec<Tranche>,
+    pub receipt_mint: Option<Pubkey>,
+    pub beneficiary: Option<Pubkey>,
+    pub beneficiaries: Vec<Beneficiary>,
+    pub revocable: bool,
+}
+
+#[event]
+pub struct Relocked {
+    pub locker: Pubkey,
+    pub old_unlock_date: i64,
+    pub new_unlock_date: i64,
+    /// Amount staying vested for lockers with linear emission.
+    pub vested_amount: u64,
+}
+
+#[event]
+pub struct OwnershipTransferred {
+    pub locker: Pubkey,
+    pub old_owner: Pubkey,
+    pub new_owner: Pubkey,
+}
+
+#[event]
+pub struct BeneficiaryChanged {
+    pub locker: Pubkey,
+    pub old_beneficiary: Option<Pubkey>,
+    pub new_beneficiary: Option<Pubkey>,
+}
+
+#[event]
+pub struct DelegateChanged {
+    pub locker: Pubkey,
+    pub delegate: Option<Pubkey>,
+    pub permissions: u8,
+}
+
+#[event]
+pub struct LockIncremented {
+    pub locker: Pubkey,
+    /// Amount taken from funding wallet including the fee.
+    pub amount: u64,
+    pub fee: u64,
+    pub deposited_amount: u64,
+}
+
+#[event]
+pub struct FundsWithdrawn {
+    pub locker: Pubkey,
+    pub owner: Pubkey,
+    pub target_wallet: Pubkey,
+    pub amount: u64,
+    pub withdrawn_amount: u64,
+}
+
+#[event]
+pub struct LockerSplit {
+    pub old_locker: Pubkey,
+    pub new_locker: Pubkey,
+    pub new_owner: Pubkey,
+    pub amount: u64,
+    pub old_deposited_amount: u64,
+    pub new_deposited_amount: u64,
+}
+
+#[event]
+pub struct Revoked {
+    pub locker: Pubkey,
+    pub creator: Pubkey,
+    /// Unvested amount returned to creator.
+    pub amount: u64,
+    pub deposited_amount: u64,
+}
+
+#[event]
+pub struct LockersMerged {
+    pub source_locker: Pubkey,
+    pub destination_locker: Pubkey,
+    /// Amount moved from source vault.
+    pub amount: u64,
+    pub deposited_amount: u64,
+    pub unlock_date: i64,
+}
+
+#[event]
+pub struct LockerClosed {
+    pub locker: Pubkey,
+    pub owner: Pubkey,
+}
+
+#[event]
+pub struct FeePaid {
+    pub payer: Pubkey,
+    pub fee_wallet: Pubkey,
+    /// `None` if the fee is paid in SOL.
+    pub mint: Option<Pubkey>,
+    pub amount: u64,
+    pub discount_bps: u16,
+    /// Wallet or token account of the referrer.
+    pub referrer: Option<Pubkey>,
+    /// Part of `amount` paid to the referrer.
+    pub referral_amount: u64,
+    /// Parts of `amount` paid to `fee_recipients` from config, in the same order.
+    pub recipient_amounts: Vec<u64>,
+}
+
+#[event]
+pub struct AdminProposed {
+    pub admin: Pubkey,
+    pub pending_admin: Pubkey,
+}
+
+#[event]
+pub struct AdminAccepted {
+    pub old_admin: Pubkey,
+    pub new_admin: Pubkey,
+}
+
+#[event]
+pub struct AdminProposalCancelled {
+    pub admin: Pubkey,
+}
+
+#[event]
+pub struct EmergencyUnlockRequested {
+    pub locker: Pubkey,
+    pub requester: Pubkey,
+}
+
+#[event]
+pub struct EmergencyUnlockApproved {
+    pub locker: Pubkey,
+    /// The earliest time the funds can be withdrawn.
+    pub unlock_after: i64,
+}
+
+#[event]
+pub struct EmergencyUnlockCancelled {
+    pub locker: Pubkey,
+    /// Requester or admin.
+    pub cancelled_by: Pubkey,
+}
+
+#[event]
+pub struct EmergencyUnlocked {
+    pub locker: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct AccountMigrated {
+    pub account: Pubkey,
+    pub version: u8,
+}
+
+#[account]
+#[derive(Debug)]
+pub struct Config {
+    version: u8,
+    admin: Pubkey,
+    /// Admin proposed by the current one, becomes admin after accepting.
+    pending_admin: Option<Pubkey>,
+    fee_in_lamports: u64,
+    fee_in_token_numerator: u64,
+    fee_in_token_denominator: u64,
+    /// Alternative to the SOL fee, in `stable_fee_mint` units.
+    fee_in_stable: u64,
+    /// Stable token the flat fee can be paid in, `None` if it's not accepted.
+    stable_fee_mint: Option<Pubkey>,
+    /// Flat fee in USD cents, paid in SOL at the `sol_price_feed` price
+    /// instead of `fee_in_lamports` if there's a feed.
+    fee_in_usd_cents: u64,
+    sol_price_feed: Option<PriceFeed>,
+    /// Fees for longer locks, base fees above are used for shorter ones.
+    fee_tiers: Vec<FeeTier>,
+    /// Part of the fees paid to the referrer if there's one.
+    referral_bps: u16,
+    /// Share the rest of the fees, fee wallet gets what is left after them.
+    fee_recipients: Vec<FeeRecipient>,
+    mint_info_permissioned: bool,
+    has_linear_emission: bool,
+    fee_wallet: Pubkey,
+    country_list: Pubkey,
+    /// Blocks any changes to lockers during an incident.
+    paused: bool,
+    /// Lets owners withdraw unlocked funds while paused.
+    allow_withdrawals_when_paused: bool,
+    bump: u8,
+    /// Room for new fields, so they can be added without reallocating.
+    reserved: [u8; 64],
+}
+
+impl Config {
+    pub const VERSION: u8 = 1;
+    pub const MAX_FEE_TIERS: usize = 8;
+    pub const MAX_FEE_RECIPIENTS: usize = 8;
+    /// Most of the fee always goes to the fee wallet and fee recipients.
+    pub const MAX_REFERRAL_BPS: u16 = 3000;
+    pub const LEN: usize = 8
+        + std::mem::size_of::<Self>()
+        + Self::MAX_FEE_TIERS * std::mem::size_of::<FeeTier>()
+        + Self::MAX_FEE_RECIPIENTS * std::mem::size_of::<FeeRecipient>();
+
+    pub fn fee_wallet(&self) -> Pubkey {
+        self.fee_wallet
+    }
+
+    pub fn fee_recipients(&self) -> &[FeeRecipient] {
+        &self.fee_recipients
+    }
+
+    pub fn country_list(&self) -> Pubkey {
+        self.country_list
+    }
+
+    /// Fees of the longest tier the lock duration reaches, base fees if none.
+    fn fee_tier(&self, lock_duration: i64) -> FeeTier {
+        self.fee_tiers
+            .iter()
+            .rev()
+            .find(|tier| tier.min_duration <= lock_duration)
+            .cloned()
+            .unwrap_or(FeeTier {
+                min_duration: 0,
+                fee_in_lamports: self.fee_in_lamports,
+                fee_in_token_numerator: self.fee_in_token_numerator,
+                fee_in_token_denominator: self.fee_in_token_denominator,
+                fee_in_stable: self.fee_in_stable,
+                fee_in_usd_cents: self.fee_in_usd_cents,
+            })
+    }
+
+    fn to_event(&self) -> ConfigUpdated {
+        ConfigUpdated {
+            admin: self.admin,
+            fee_in_lamports: self.fee_in_lamports,
+            fee_in_token_numerator: self.fee_in_token_numerator,
+            fee_in_token_denominator: self.fee_in_token_denominator,
+            fee_in_stable: self.fee_in_stable,
+            stable_fee_mint: self.stable_fee_mint,
+            fee_in_usd_cents: self.fee_in_usd_cents,
+            sol_price_feed: self.sol_price_feed,
+            fee_tiers: self.fee_tiers.clone(),
+            referral_bps: self.referral_bps,
+            fee_recipients: self.fee_recipients.clone(),
+            mint_info_permissioned: self.mint_info_permissioned,
+            has_linear_emission: self.has_linear_emission,
+            fee_wallet: self.fee_wallet,
+            country_list: self.country_list,
+            paused: self.paused,
+            allow_withdrawals_when_paused: self.allow_withdrawals_when_paused,
+        }
+    }
+}
+
+/// Fees for locks not shorter than `min_duration` seconds.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct FeeTier {
+    pub min_duration: i64,
+    pub fee_in_lamports: u64,
+    pub fee_in_token_numerator: u64,
+    pub fee_in_token_denominator: u64,
+    pub fee_in_stable: u64,
+    pub fee_in_usd_cents: u64,
+}
+
+/// Pyth price account of SOL in USD and limits for prices read from it.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug)]
+pub struct PriceFeed {
+    pub account: Pubkey,
+    /// Prices published earlier than that many seconds ago are rejected.
+    pub max_age: i64,
+    /// Prices with confidence interval wider than that part of them are rejected.
+    pub max_confidence_bps: u16,
+}
+
+/// Gets `bps` of the fees, to `wallet` for fees in SOL and to its
+/// associated token account for fees in tokens.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct FeeRecipient {
+    pub wallet: Pubkey,
+    pub bps: u16,
 }
 
 #[derive(AnchorDeserialize, AnchorSerialize)]
 pub struct CreateConfigArgs {
-    pub fee_in_sol: u64,
+    pub fee_in_lamports: u64,
     pub fee_in_token_numerator: u64,
     pub fee_in_token_denominator: u64,
     pub mint_info_permissioned: bool,
@@ -461,13 +1848,24 @@ pub struct InitConfig<'info> {
     system_program: Program<'info, System>,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize)]
+#[derive(AnchorSerialize, AnchorDeserialize, Default)]
 pub struct UpdateConfigArgs {
-    fee_in_sol: Option<u64>,
-    fee_in_token_numerator: Option<u64>,
-    fee_in_token_denominator: Option<u64>,
-    mint_info_permissioned: Option<bool>,
-    has_linear_emission: Option<bool>,
+    pub fee_in_lamports: Option<u64>,
+    pub fee_in_token_numerator: Option<u64>,
+    pub fee_in_token_denominator: Option<u64>,
+    pub fee_in_stable: Option<u64>,
+    /// Default pubkey turns the fee in stable token off.
+    pub stable_fee_mint: Option<Pubkey>,
+    pub fee_in_usd_cents: Option<u64>,
+    /// Default account turns the feed off.
+    pub sol_price_feed: Option<PriceFeed>,
+    pub fee_tiers: Option<Vec<FeeTier>>,
+    pub referral_bps: Option<u16>,
+    pub fee_recipients: Option<Vec<FeeRecipient>>,
+    pub mint_info_permissioned: Option<bool>,
+    pub has_linear_emission: Option<bool>,
+    pub paused: Option<bool>,
+    pub allow_withdrawals_when_paused: Option<bool>,
 }
 
 #[derive(Accounts)]
@@ -489,32 +1887,530 @@ pub struct UpdateConfig<'info> {
     country_list: Account<'info, country_list::CountryBanList>,
 }
 
+#[derive(Accounts)]
+pub struct ProposeAdmin<'info> {
+    #[account(signer)]
+    admin: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [
+            "config".as_ref()
+        ],
+        bump = config.bump,
+        constraint = config.admin == admin.key()
+    )]
+    config: ProgramAccount<'info, Config>,
+    new_admin: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAdmin<'info> {
+    #[account(signer)]
+    new_admin: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [
+            "config".as_ref()
+        ],
+        bump = config.bump,
+        constraint = config.pending_admin == Some(new_admin.key())
+    )]
+    config: ProgramAccount<'info, Config>,
+}
+
+#[derive(Accounts)]
+pub struct CancelAdminProposal<'info> {
+    #[account(signer)]
+    admin: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [
+            "config".as_ref()
+        ],
+        bump = config.bump,
+        constraint = config.admin == admin.key()
+    )]
+    config: ProgramAccount<'info, Config>,
+}
+
+/// Fixed size fields go first, so they can be used in `memcmp` filters,
+/// see `Locker::*_OFFSET`.
 #[account]
+#[derive(Debug)]
 pub struct Locker {
+    version: u8,
     owner: Pubkey,
+    creator: Pubkey,
+    mint: Pubkey,
+    vault: Pubkey,
+    vault_bump: u8,
+    /// Creator can take back unvested tokens.
+    revocable: bool,
     country_code: [u8; 2],
     current_unlock_date: i64,
-    start_emission: Option<i64>,
     deposited_amount: u64,
-    vault: Pubkey,
-    vault_bump: u8,
+    withdrawn_amount: u64,
+    /// Wallet receiving withdrawn tokens, the one managing the locker if not set.
+    beneficiary: Option<Pubkey>,
+    start_emission: Option<i64>,
+    cliff: Option<Cliff>,
+    schedule: Vec<Tranche>,
+    /// Mint of 1-of-1 receipt token, its holder manages the locker instead of owner.
+    receipt_mint: Option<Pubkey>,
+    /// If not empty, vested tokens are withdrawn by beneficiaries according
+    /// to their shares, while owner can only relock the locker.
+    beneficiaries: Vec<Beneficiary>,
+    /// Wallet managing the locker on behalf of the owner.
+    delegate: Option<Pubkey>,
+    /// Bitmask of `PERMISSION_*` operations allowed to the delegate.
+    delegate_permissions: u8,
+    /// Amount vested when the locker with linear emission was relocked,
+    /// the rest vests linearly from `start_emission`.
+    vested_at_relock: u64,
+    /// Room for new fields, so they can be added without reallocating.
+    reserved: [u8; 56],
 }
 
 impl Locker {
     pub const LEN: usize = std::mem::size_of::<Self>() + 8;
-}
-
-#[account]
+    pub const MAX_TRANCHES: usize = 64;
+    pub const MAX_BENEFICIARIES: usize = 16;
+
+    pub const SEED: &[u8] = b"locker";
+    pub const VERSION: u8 = 1;
+
+    // offsets include 8 bytes of account discriminator
+    pub const OWNER_OFFSET: usize = 9;
+    pub const CREATOR_OFFSET: usize = 41;
+    pub const MINT_OFFSET: usize = 73;
+    pub const VAULT_OFFSET: usize = 105;
+
+    pub const PERMISSION_RELOCK: u8 = 1 << 0;
+    pub const PERMISSION_INCREMENT: u8 = 1 << 1;
+    pub const PERMISSION_SPLIT: u8 = 1 << 2;
+    /// Withdraw to the owner or beneficiary of the locker.
+    pub const PERMISSION_WITHDRAW: u8 = 1 << 3;
+    pub const PERMISSION_ALL: u8 = Self::PERMISSION_RELOCK
+        | Self::PERMISSION_INCREMENT
+        | Self::PERMISSION_SPLIT
+        | Self::PERMISSION_WITHDRAW;
+
+    /// Lockers are derived from the creator rather than the owner,
+    /// since the owner can change while the address can not.
+    pub fn find_address(
+        creator: &Pubkey,
+        mint: &Pubkey,
+        nonce: u64,
+        program_id: &Pubkey,
+    ) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[
+                Self::SEED,
+                creator.as_ref(),
+                mint.as_ref(),
+                nonce.to_be_bytes().as_ref(),
+            ],
+            program_id,
+        )
+    }
+
+    pub fn space(schedule_len: usize, beneficiaries_len: usize) -> usize {
+        Self::LEN
+            + schedule_len * std::mem::size_of::<Tranche>()
+            + beneficiaries_len * std::mem::size_of::<Beneficiary>()
+    }
+
+    /// Lockers with receipt are managed by whoever holds the receipt token,
+    /// so one of the remaining accounts should be the holder's token account.
+    pub fn require_receipt_holder(
+        &self,
+        holder: &Pubkey,
+        remaining_accounts: &[AccountInfo],
+    ) -> Result<()> {
+        if let Some(receipt_mint) = self.receipt_mint {
+            let holds_receipt = remaining_accounts
+                .iter()
+                .filter_map(|account| token_interface::TokenAccount::load(account).ok())
+                .any(|account| {
+                    account.mint == receipt_mint && account.owner == *holder && account.amount == 1
+                });
+            require!(holds_receipt, ReceiptNotHeld);
+        }
+
+        Ok(())
+    }
+
+    /// Tokens still in the locker by its own accounting.
+    pub fn remaining_amount(&self) -> u64 {
+        self.deposited_amount.saturating_sub(self.withdrawn_amount)
+    }
+
+    pub fn is_delegate(&self, key: &Pubkey, permission: u8) -> bool {
+        self.delegate == Some(*key) && self.delegate_permissions & permission != 0
+    }
+
+    /// Whether tokens are unlocked gradually rather than at unlock date.
+    pub fn has_emission(&self) -> bool {
+        self.start_emission.is_some() || !self.schedule.is_empty()
+    }
+
+    /// Amount unlocked by the given time, including already withdrawn tokens.
+    pub fn vested_amount(&self, now: i64) -> Result<u64> {
+        if !self.schedule.is_empty() {
+            let unlocked_bps = self
+                .schedule
+                .iter()
+                .take_while(|tranche| tranche.date <= now)
+                .last()
+                .map_or(0, |tranche| tranche.bps);
+
+            let vested_amount = mul_div(self.deposited_amount, unlocked_bps, BASIS_POINTS)
+                .ok_or(ErrorCode::IntegerOverflow)?;
+
+            return Ok(vested_amount);
+        }
+
+        match self.start_emission {
+            Some(start_emission) => {
+                // with cliff nothing is unlocked before the cliff date,
+                // then the cliff part at once and the rest linearly,
+                // relocked locker starts with the amount vested by then
+                let (emission_start, cliff_amount) = match self.cliff {
+                    Some(cliff) if now < cliff.date => return Ok(0),
+                    Some(cliff) => (
+                        cliff.date,
+                        mul_div(self.deposited_amount, cliff.bps, BASIS_POINTS)
+                            .ok_or(ErrorCode::IntegerOverflow)?,
+                    ),
+                    None => (start_emission, self.vested_at_relock),
+                };
+
+                let clamped_time = now.clamp(emission_start, self.current_unlock_date);
+                let elapsed = clamped_time - emission_start;
+                let full_period = self.current_unlock_date - emission_start;
+                require!(full_period > 0, InvalidPeriod);
+
+                let emitted_amount = mul_div(
+                    self.deposited_amount - cliff_amount,
+                    elapsed,
+                    full_period as u64,
+                )
+                .ok_or(ErrorCode::IntegerOverflow)?;
+
+                Ok(cliff_amount + emitted_amount)
+            }
+            None if now > self.current_unlock_date => Ok(self.deposited_amount),
+            None => Ok(0),
+        }
+    }
+
+    /// Part of vested amount the beneficiary can withdraw at the given time.
+    fn beneficiary_available_amount(&self, index: usize, now: i64) -> Result<u64> {
+        let vested_amount = self.vested_amount(now)?;
+
+        let shares_before: u64 = self.beneficiaries[..index]
+            .iter()
+            .map(|b| b.share_bps as u64)
+            .sum();
+        let shares_after = shares_before + self.beneficiaries[index].share_bps as u64;
+
+        // rounding cumulative shares makes parts add up to vested amount exactly
+        let vested_share = mul_div(vested_amount, shares_after, BASIS_POINTS)
+            .ok_or(ErrorCode::IntegerOverflow)?
+            - mul_div(vested_amount, shares_before, BASIS_POINTS)
+                .ok_or(ErrorCode::IntegerOverflow)?;
+
+        // the share may lag behind by a token due to rounding
+        Ok(vested_share.saturating_sub(self.beneficiaries[index].withdrawn_amount))
+    }
+
+    /// Moves the unlock date later. Lockers with linear emission keep
+    /// the amount vested by now and vest the rest from now till the new date,
+    /// otherwise extending the period would take back part of vested tokens.
+    fn relock(&mut self, unlock_date: i64, now: i64) -> Result<()> {
+        require!(
+            unlock_date > self.current_unlock_date,
+            CannotUnlockToEarlierDate
+        );
+        // tranches have their own dates, moving only the unlock date
+        // would change nothing
+        require!(self.schedule.is_empty(), CannotRelockSchedule);
+
+        if let Some(start_emission) = self.start_emission {
+            let emission_start = self.cliff.map_or(start_emission, |cliff| cliff.date);
+            // nothing is vested before the emission starts, so the period
+            // can be just extended
+            if now > emission_start {
+                require!(unlock_date > now, UnlockInThePast);
+
+                self.vested_at_relock = self.vested_amount(now)?;
+                self.start_emission = Some(now);
+                self.cliff = None;
+            }
+        }
+
+        self.current_unlock_date = unlock_date;
+
+        Ok(())
+    }
+
+    /// Takes `amount` of remaining tokens out of the locker together with
+    /// the proportional part of deposited, withdrawn and vested at relock
+    /// amounts, so both parts keep the same vested/withdrawn ratio.
+    ///
+    /// Returns deposited, withdrawn and vested at relock amounts
+    /// for the new locker.
+    fn split_off(&mut self, amount: u64) -> Result<(u64, u64, u64)> {
+        require!(self.beneficiaries.is_empty(), LockerHasBeneficiaries);
+
+        let remaining_amount = self
+            .deposited_amount
+            .checked_sub(self.withdrawn_amount)
+            .ok_or(ErrorCode::IntegerOverflow)?;
+        require!(amount <= remaining_amount, InvalidAmount);
+
+        // rounding down what this locker keeps never raises its withdrawn
+        // part above the vested one, the new locker takes the rest
+        let kept_amount = mul_div(
+            self.deposited_amount,
+            remaining_amount - amount,
+            remaining_amount,
+        )
+        .ok_or(ErrorCode::IntegerOverflow)?;
+        // always >= amount since deposited >= remaining
+        let deposited_amount = self.deposited_amount - kept_amount;
+        let withdrawn_amount = deposited_amount - amount;
+        // rounded down for the new locker, so this one keeps at least
+        // its part of vested at relock amount next to the rounded down
+        // deposited amount
+        let vested_at_relock = mul_div(
+            self.vested_at_relock,
+            deposited_amount,
+            self.deposited_amount,
+        )
+        .ok_or(ErrorCode::IntegerOverflow)?;
+
+        self.deposited_amount -= deposited_amount;
+        self.withdrawn_amount -= withdrawn_amount;
+        self.vested_at_relock -= vested_at_relock;
+
+        Ok((deposited_amount, withdrawn_amount, vested_at_relock))
+    }
+
+    /// Cuts the locker down to the amount vested by now, which becomes
+    /// unlocked right away. Returns unvested amount.
+    fn revoke(&mut self, now: i64) -> Result<u64> {
+        // tokens withdrawn ahead of vesting due to rounding stay vested,
+        // so the unvested part never exceeds what's in the vault
+        let vested_amount = self.vested_amount(now)?.max(self.withdrawn_amount);
+        let unvested_amount = self
+            .deposited_amount
+            .checked_sub(vested_amount)
+            .ok_or(ErrorCode::IntegerOverflow)?;
+
+        self.deposited_amount = vested_amount;
+        self.current_unlock_date = now - 1;
+        self.start_emission = None;
+        self.cliff = None;
+        self.schedule.clear();
+        self.vested_at_relock = 0;
+        self.revocable = false;
+
+        Ok(unvested_amount)
+    }
+
+    /// Adds tokens of other locker to this one. Lockers with emission
+    /// should vest the same way, time locks keep the later unlock date.
+    fn merge(&mut self, other: &Locker) -> Result<()> {
+        require!(self.mint == other.mint, InvalidMerge);
+        require!(
+            self.beneficiaries.is_empty() && other.beneficiaries.is_empty(),
+            LockerHasBeneficiaries
+        );
+        // tokens should not become revocable or escape revocation
+        require!(
+            self.revocable == other.revocable && (!self.revocable || self.creator == other.creator),
+            InvalidMerge
+        );
+
+        if self.has_emission() || other.has_emission() {
+            require!(
+                self.current_unlock_date == other.current_unlock_date
+                    && self.start_emission == other.start_emission
+                    && self.cliff == other.cliff
+                    && self.schedule == other.schedule,
+                InvalidMerge
+            );
+        }
+
+        self.current_unlock_date = self.current_unlock_date.max(other.current_unlock_date);
+        self.deposited_amount = self
+            .deposited_amount
+            .checked_add(other.deposited_amount)
+            .ok_or(ErrorCode::IntegerOverflow)?;
+        self.withdrawn_amount = self
+            .withdrawn_amount
+            .checked_add(other.withdrawn_amount)
+            .ok_or(ErrorCode::IntegerOverflow)?;
+        // vesting is linear in amounts when the periods match
+        self.vested_at_relock = self
+            .vested_at_relock
+            .checked_add(other.vested_at_relock)
+            .ok_or(ErrorCode::IntegerOverflow)?;
+
+        Ok(())
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Cliff {
+    date: i64,
+    /// Part of deposited amount unlocked at cliff date.
+    bps: u16,
+}
+
+/// Unlock step of a schedule, `bps` is the cumulative part of
+/// deposited amount unlocked at `date`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Tranche {
+    date: i64,
+    bps: u16,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug)]
+pub struct Beneficiary {
+    owner: Pubkey,
+    share_bps: u16,
+    withdrawn_amount: u64,
+}
+
+/// Beneficiary share of a new locker.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug)]
+pub struct Share {
+    owner: Pubkey,
+    bps: u16,
+}
+
+#[account]
+#[derive(Debug)]
 pub struct MintInfo {
+    version: u8,
     bump: u8,
     fee_paid: bool,
+    /// Tokens remaining in lockers of the mint, except revocable ones.
+    /// Vested tokens of lockers with emission stay here until withdrawn.
+    total_locked: u64,
+    locker_count: u64,
+    /// Only a lower bound of unlock dates: it's the earliest date of lockers
+    /// added since the mint had none, so it is not moved forward when such
+    /// lockers are withdrawn or relocked. Zero if there are no lockers.
+    unlock_date_lower_bound: i64,
+    /// Sum of remaining amounts multiplied by unlock dates.
+    weighted_unlock_sum: u128,
+    /// Tokens remaining in revocable lockers, creators can take back
+    /// the unvested part of them.
+    revocable_locked: u64,
+    /// Room for new fields, so they can be added without reallocating.
+    reserved: [u8; 56],
 }
 
 impl Default for MintInfo {
     fn default() -> Self {
         Self {
+            version: Self::VERSION,
             bump: Default::default(),
             fee_paid: Default::default(),
+            total_locked: Default::default(),
+            locker_count: Default::default(),
+            unlock_date_lower_bound: Default::default(),
+            weighted_unlock_sum: Default::default(),
+            revocable_locked: Default::default(),
+            reserved: [0; 56],
+        }
+    }
+}
+
+impl MintInfo {
+    pub const VERSION: u8 = 1;
+    /// Serialized fields after the discriminator, the same `init` allocates.
+    pub const LEN: usize = 8 + 1 + 1 + 1 + 8 + 8 + 8 + 16 + 8 + 56;
+
+    pub fn total_locked(&self) -> u64 {
+        self.total_locked
+    }
+
+    pub fn locker_count(&self) -> u64 {
+        self.locker_count
+    }
+
+    pub fn revocable_locked(&self) -> u64 {
+        self.revocable_locked
+    }
+
+    /// No locker unlocks earlier, though all of them may unlock later.
+    pub fn unlock_date_lower_bound(&self) -> Option<i64> {
+        if self.locker_count == 0 {
+            return None;
+        }
+        Some(self.unlock_date_lower_bound)
+    }
+
+    /// Unlock date weighted by remaining amounts.
+    pub fn average_unlock_date(&self) -> Option<i64> {
+        if self.total_locked == 0 {
+            return None;
+        }
+        Some((self.weighted_unlock_sum / self.total_locked as u128) as i64)
+    }
+
+    /// Counts the locker in. Any change of locker amounts or unlock date
+    /// should go between `remove_locker` and `add_locker`.
+    fn add_locker(&mut self, locker: &Locker) -> Result<()> {
+        let remaining_amount = locker.remaining_amount();
+        let unlock_date = locker.current_unlock_date.max(0);
+
+        if locker.revocable {
+            self.revocable_locked = self
+                .revocable_locked
+                .checked_add(remaining_amount)
+                .ok_or(ErrorCode::IntegerOverflow)?;
+        } else {
+            self.total_locked = self
+                .total_locked
+                .checked_add(remaining_amount)
+                .ok_or(ErrorCode::IntegerOverflow)?;
+            self.weighted_unlock_sum = self
+                .weighted_unlock_sum
+                .checked_add(remaining_amount as u128 * unlock_date as u128)
+                .ok_or(ErrorCode::IntegerOverflow)?;
+        }
+        self.unlock_date_lower_bound = if self.locker_count == 0 {
+            unlock_date
+        } else {
+            self.unlock_date_lower_bound.min(unlock_date)
+        };
+        self.locker_count += 1;
+
+        Ok(())
+    }
+
+    /// Stats are informational, so they never block withdrawals: for
+    /// lockers created before they were tracked the counters saturate at zero.
+    fn remove_locker(&mut self, locker: &Locker) {
+        let remaining_amount = locker.remaining_amount();
+        let unlock_date = locker.current_unlock_date.max(0);
+
+        if locker.revocable {
+            self.revocable_locked = self.revocable_locked.saturating_sub(remaining_amount);
+        } else {
+            self.total_locked = self.total_locked.saturating_sub(remaining_amount);
+            self.weighted_unlock_sum = self
+                .weighted_unlock_sum
+                .saturating_sub(remaining_amount as u128 * unlock_date as u128);
+        }
+        self.locker_count = self.locker_count.saturating_sub(1);
+        if self.locker_count == 0 {
+            self.unlock_date_lower_bound = 0;
         }
     }
 }
@@ -533,235 +2429,905 @@ pub struct InitMintInfo<'info> {
         bump = bump
     )]
     mint_info: ProgramAccount<'info, MintInfo>,
-    mint: Account<'info, Mint>,
+    #[account(
+        constraint = token_interface::is_token_program(mint.owner)
+    )]
+    mint: AccountInfo<'info>,
     config: ProgramAccount<'info, Config>,
 
     system_program: Program<'info, System>,
 }
 
+/// Fee discount set by admin for lockers created by the wallet.
+#[account]
+#[derive(Debug)]
+pub struct FeeExemption {
+    wallet: Pubkey,
+    /// Part of the fees not taken from the wallet, 10000 means no fees at all.
+    discount_bps: u16,
+    bump: u8,
+}
+
+impl FeeExemption {
+    pub const LEN: usize = 8 + std::mem::size_of::<Self>();
+}
+
+/// Wallet registered by admin to get part of the fees of lockers it refers.
+#[account]
+#[derive(Debug)]
+pub struct Referrer {
+    wallet: Pubkey,
+    bump: u8,
+}
+
+impl Referrer {
+    pub const LEN: usize = 8 + std::mem::size_of::<Self>();
+}
+
+/// Owner's request to withdraw everything from a locker before its unlock date.
+#[account]
+#[derive(Debug)]
+pub struct EmergencyUnlock {
+    locker: Pubkey,
+    requester: Pubkey,
+    requested_at: i64,
+    /// Set by admin, funds can leave the vault `DELAY` seconds later.
+    approved_at: Option<i64>,
+    bump: u8,
+}
+
+impl EmergencyUnlock {
+    pub const LEN: usize = 8 + std::mem::size_of::<Self>();
+    /// Gives holders of the token time to notice the approval
+    /// and admin time to cancel it.
+    #[cfg(not(feature = "test-instructions"))]
+    pub const DELAY: i64 = 7 * 24 * 60 * 60;
+    /// Short enough for tests to wait it out.
+    #[cfg(feature = "test-instructions")]
+    pub const DELAY: i64 = 5;
+
+    pub fn requester(&self) -> Pubkey {
+        self.requester
+    }
+}
+
+#[event]
+pub struct FeeExemptionUpdated {
+    pub wallet: Pubkey,
+    /// Zero when the exemption is removed.
+    pub discount_bps: u16,
+}
+
 #[derive(AnchorSerialize, AnchorDeserialize)]
-pub struct CreateLockerArgs {
-    amount: u64,
-    unlock_date: i64,
-    country_code: String,
-    start_emission: Option<i64>,
-    vault_bump: u8,
-    fee_in_sol: bool,
+pub struct AddFeeExemptionArgs {
+    pub discount_bps: u16,
+    pub bump: u8,
 }
 
 #[derive(Accounts)]
-#[instruction(args: CreateLockerArgs)]
-pub struct CreateLocker<'info> {
+#[instruction(args: AddFeeExemptionArgs)]
+pub struct AddFeeExemption<'info> {
+    #[account(mut, signer)]
+    admin: AccountInfo<'info>,
+    #[account(
+        seeds = [
+            "config".as_ref()
+        ],
+        bump = config.bump,
+        constraint = config.admin == admin.key()
+    )]
+    config: ProgramAccount<'info, Config>,
+    wallet: AccountInfo<'info>,
     #[account(
         init,
-        payer = creator,
-        space = Locker::LEN,
+        payer = admin,
+        seeds = [
+            "fee_exemption".as_ref(),
+            wallet.key().as_ref()
+        ],
+        bump = args.bump,
+        space = FeeExemption::LEN
     )]
-    locker: ProgramAccount<'info, Locker>,
-    #[account(signer)]
-    creator: AccountInfo<'info>,
-    owner: AccountInfo<'info>,
+    fee_exemption: ProgramAccount<'info, FeeExemption>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateFeeExemption<'info> {
     #[account(signer)]
-    funding_wallet_authority: AccountInfo<'info>,
-    #[account(mut)]
-    funding_wallet: Account<'info, TokenAccount>,
+    admin: AccountInfo<'info>,
     #[account(
         seeds = [
-            locker.key().as_ref()
+            "config".as_ref()
         ],
-        bump = args.vault_bump
+        bump = config.bump,
+        constraint = config.admin == admin.key()
     )]
-    vault_authority: AccountInfo<'info>,
+    config: ProgramAccount<'info, Config>,
     #[account(
         mut,
-        constraint = vault.mint == funding_wallet.mint,
-        constraint = vault.owner == vault_authority.key()
+        seeds = [
+            "fee_exemption".as_ref(),
+            fee_exemption.wallet.as_ref()
+        ],
+        bump = fee_exemption.bump
     )]
-    vault: Account<'info, TokenAccount>,
-    #[account(mut)]
-    fee_wallet: AccountInfo<'info>,
-    #[account(mut)]
-    fee_token_wallet: Account<'info, TokenAccount>,
+    fee_exemption: ProgramAccount<'info, FeeExemption>,
+}
+
+#[derive(Accounts)]
+pub struct RemoveFeeExemption<'info> {
+    #[account(mut, signer)]
+    admin: AccountInfo<'info>,
+    #[account(
+        seeds = [
+            "config".as_ref()
+        ],
+        bump = config.bump,
+        constraint = config.admin == admin.key()
+    )]
+    config: ProgramAccount<'info, Config>,
     #[account(
         mut,
         seeds = [
-            vault.mint.key().as_ref()
+            "fee_exemption".as_ref(),
+            fee_exemption.wallet.as_ref()
         ],
-        bump = mint_info.bump
+        bump = fee_exemption.bump
+    )]
+    fee_exemption: ProgramAccount<'info, FeeExemption>,
+}
+
+#[event]
+pub struct ReferrerUpdated {
+    pub wallet: Pubkey,
+    /// False when the referrer is removed.
+    pub registered: bool,
+}
+
+#[derive(Accounts)]
+#[instruction(bump: u8)]
+pub struct AddReferrer<'info> {
+    #[account(mut, signer)]
+    admin: AccountInfo<'info>,
+    #[account(
+        seeds = [
+            "config".as_ref()
+        ],
+        bump = config.bump,
+        constraint = config.admin == admin.key()
     )]
-    mint_info: ProgramAccount<'info, MintInfo>,
     config: ProgramAccount<'info, Config>,
+    wallet: AccountInfo<'info>,
     #[account(
-        constraint = country_banlist.key() == config.country_list
+        init,
+        payer = admin,
+        seeds = [
+            "referrer".as_ref(),
+            wallet.key().as_ref()
+        ],
+        bump = bump,
+        space = Referrer::LEN
     )]
-    country_banlist: Account<'info, country_list::CountryBanList>,
+    referrer: ProgramAccount<'info, Referrer>,
 
-    clock: Sysvar<'info, Clock>,
     system_program: Program<'info, System>,
-    token_program: Program<'info, Token>,
 }
 
 #[derive(Accounts)]
-pub struct Relock<'info> {
-    #[account(mut)]
-    locker: ProgramAccount<'info, Locker>,
+pub struct RemoveReferrer<'info> {
+    #[account(mut, signer)]
+    admin: AccountInfo<'info>,
     #[account(
-        signer,
-        constraint = locker.owner == owner.key()
+        seeds = [
+            "config".as_ref()
+        ],
+        bump = config.bump,
+        constraint = config.admin == admin.key()
     )]
-    owner: AccountInfo<'info>,
+    config: ProgramAccount<'info, Config>,
+    #[account(
+        mut,
+        seeds = [
+            "referrer".as_ref(),
+            referrer.wallet.as_ref()
+        ],
+        bump = referrer.bump
+    )]
+    referrer: ProgramAccount<'info, Referrer>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct CreateLockerArgs {
+    amount: u64,
+    unlock_date: i64,
+    country_code: String,
+    start_emission: Option<i64>,
+    cliff: Option<Cliff>,
+    schedule: Vec<Tranche>,
+    vault_bump: u8,
+    /// Any number not used by the creator for this mint yet.
+    nonce: u64,
+    locker_bump: u8,
+    /// Pay the flat fee instead of part of locked tokens.
+    fee_in_sol: bool,
+    /// Pay the flat fee in stable token from config instead of SOL, so
+    /// `fee_in_sol` should be set too. Its mint, token program and associated
+    /// token accounts of creator, fee wallet, referrer and fee recipients
+    /// should be among remaining accounts.
+    fee_in_stable: bool,
+    /// Mint a receipt token to the owner, mint and owner's token account
+    /// for it should be the first remaining accounts.
+    receipt: bool,
+    /// Wallet receiving withdrawn tokens if it should differ from owner.
+    beneficiary: Option<Pubkey>,
+    /// Allow creator to revoke unvested tokens.
+    revocable: bool,
+    /// Shares should add up to 100%, empty for lockers withdrawn by owner.
+    beneficiaries: Vec<Share>,
+    /// Gets part of the fee, its referrer account and its wallet or token
+    /// account for fees in tokens should be among remaining accounts.
+    referrer: Option<Pubkey>,
+}
+
+#[derive(Accounts)]
+#[instruction(args: CreateLockerArgs)]
+pub struct CreateLocker<'info> {
+    #[account(
+        init,
+        payer = creator,
+        seeds = [
+            Locker::SEED,
+            creator.key().as_ref(),
+            mint.key().as_ref(),
+            args.nonce.to_be_bytes().as_ref()
+        ],
+        bump = args.locker_bump,
+        space = Locker::space(args.schedule.len(), args.beneficiaries.len()),
+    )]
+    locker: ProgramAccount<'info, Locker>,
+    #[account(mut, signer)]
+    creator: AccountInfo<'info>,
+    owner: AccountInfo<'info>,
+    #[account(signer)]
+    funding_wallet_authority: AccountInfo<'info>,
+    #[account(mut)]
+    funding_wallet: AccountInfo<'info>,
+    #[account(
+        seeds = [
+            locker.key().as_ref()
+        ],
+        bump = args.vault_bump
+    )]
+    vault_authority: AccountInfo<'info>,
+    /// Associated token account of vault authority.
+    #[account(
+        mut,
+        constraint = vault.key()
+            == token_interface::associated_token_address(
+                vault_authority.key,
+                mint.key,
+                token_program.key
+            )
+    )]
+    vault: AccountInfo<'info>,
+    #[account(mut)]
+    fee_wallet: AccountInfo<'info>,
+    #[account(mut)]
+    fee_token_wallet: AccountInfo<'info>,
+    #[account(
+        constraint = *mint.owner == token_program.key()
+    )]
+    mint: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [
+            mint.key().as_ref()
+        ],
+        bump = mint_info.bump
+    )]
+    mint_info: ProgramAccount<'info, MintInfo>,
+    config: ProgramAccount<'info, Config>,
+    #[account(
+        constraint = country_banlist.key() == config.country_list
+    )]
+    country_banlist: Account<'info, country_list::CountryBanList>,
+
+    clock: Sysvar<'info, Clock>,
+    system_program: Program<'info, System>,
+    #[account(
+        constraint = token_interface::is_token_program(token_program.key)
+    )]
+    token_program: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Relock<'info> {
+    #[account(mut)]
+    locker: ProgramAccount<'info, Locker>,
+    #[account(
+        signer,
+        constraint = locker.receipt_mint.is_some()
+            || locker.owner == owner.key()
+            || locker.is_delegate(owner.key, Locker::PERMISSION_RELOCK)
+    )]
+    owner: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [
+            locker.mint.as_ref()
+        ],
+        bump = mint_info.bump
+    )]
+    mint_info: ProgramAccount<'info, MintInfo>,
+
+    clock: Sysvar<'info, Clock>,
+}
+
+#[derive(Accounts)]
+pub struct TransferOwnership<'info> {
+    #[account(mut)]
+    locker: ProgramAccount<'info, Locker>,
+    #[account(
+        signer,
+        constraint = !locker.beneficiaries.is_empty() || locker.owner == owner.key()
+    )]
+    owner: AccountInfo<'info>,
+    new_owner: AccountInfo<'info>,
+    config: ProgramAccount<'info, Config>,
+}
+
+#[derive(Accounts)]
+pub struct SetBeneficiary<'info> {
+    #[account(mut)]
+    locker: ProgramAccount<'info, Locker>,
+    #[account(
+        signer,
+        constraint = locker.receipt_mint.is_some() || locker.owner == owner.key()
+    )]
+    owner: AccountInfo<'info>,
+    config: ProgramAccount<'info, Config>,
+}
+
+#[derive(Accounts)]
+pub struct SetDelegate<'info> {
+    #[account(mut)]
+    locker: ProgramAccount<'info, Locker>,
+    #[account(
+        signer,
+        constraint = locker.owner == owner.key()
+    )]
+    owner: AccountInfo<'info>,
+    config: ProgramAccount<'info, Config>,
+}
+
+#[derive(Accounts)]
+pub struct IncrementLock<'info> {
+    #[account(mut)]
+    locker: ProgramAccount<'info, Locker>,
+    #[account(
+        mut,
+        constraint = locker.vault == vault.key()
+    )]
+    vault: AccountInfo<'info>,
+    #[account(
+        constraint = *mint.owner == token_program.key(),
+        constraint = locker.mint == mint.key()
+    )]
+    mint: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [
+            mint.key().as_ref()
+        ],
+        bump = mint_info.bump
+    )]
+    mint_info: ProgramAccount<'info, MintInfo>,
+    #[account(
+        signer,
+        constraint = locker.receipt_mint.is_some()
+            || locker.owner == funding_wallet_authority.key()
+            || locker.is_delegate(funding_wallet_authority.key, Locker::PERMISSION_INCREMENT)
+    )]
+    funding_wallet_authority: AccountInfo<'info>,
+    #[account(mut)]
+    funding_wallet: AccountInfo<'info>,
+    #[account(mut)]
+    fee_wallet: AccountInfo<'info>,
+    config: ProgramAccount<'info, Config>,
+
+    clock: Sysvar<'info, Clock>,
+    #[account(
+        constraint = token_interface::is_token_program(token_program.key)
+    )]
+    token_program: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawFunds<'info> {
+    #[account(mut)]
+    locker: ProgramAccount<'info, Locker>,
+    #[account(
+        signer,
+        constraint = locker.receipt_mint.is_some()
+            || !locker.beneficiaries.is_empty()
+            || locker.owner == owner.key()
+            || locker.is_delegate(owner.key, Locker::PERMISSION_WITHDRAW)
+    )]
+    owner: AccountInfo<'info>,
+    /// Gets the rent back when the locker is closed.
+    #[account(
+        mut,
+        constraint = locker_owner.key() == locker.owner
+    )]
+    locker_owner: AccountInfo<'info>,
+    /// This authority allows the program to sign token transfer
+    /// back to target wallet.
+    vault_authority: AccountInfo<'info>,
+    #[account(
+        mut,
+        constraint = locker.vault == vault.key()
+    )]
+    vault: AccountInfo<'info>,
+    #[account(
+        constraint = *mint.owner == token_program.key(),
+        constraint = locker.mint == mint.key()
+    )]
+    mint: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [
+            mint.key().as_ref()
+        ],
+        bump = mint_info.bump
+    )]
+    mint_info: ProgramAccount<'info, MintInfo>,
+    #[account(mut)]
+    target_wallet: AccountInfo<'info>,
+    config: ProgramAccount<'info, Config>,
+
+    clock: Sysvar<'info, Clock>,
+    #[account(
+        constraint = token_interface::is_token_program(token_program.key)
+    )]
+    token_program: AccountInfo<'info>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SplitLockerArgs {
+    locker_bump: u8,
+    vault_bump: u8,
+    amount: u64,
+    /// Relocks only the split off part till this date.
+    unlock_date: Option<i64>,
+}
+
+#[derive(Accounts)]
+#[instruction(args: SplitLockerArgs)]
+pub struct SplitLocker<'info> {
+    #[account(mut)]
+    old_locker: ProgramAccount<'info, Locker>,
+    #[account(
+        signer,
+        constraint = old_locker.receipt_mint.is_some()
+            || old_locker.owner == old_owner.key()
+            || old_locker.is_delegate(old_owner.key, Locker::PERMISSION_SPLIT)
+    )]
+    old_owner: AccountInfo<'info>,
+    /// Gets the rent back when the old locker is closed.
+    #[account(
+        mut,
+        constraint = locker_owner.key() == old_locker.owner
+    )]
+    locker_owner: AccountInfo<'info>,
+    /// This authority allows the program to sign token transfer
+    /// back to target wallet.
+    old_vault_authority: AccountInfo<'info>,
+    #[account(
+        mut,
+        constraint = old_locker.vault == old_vault.key()
+    )]
+    old_vault: AccountInfo<'info>,
+    #[account(
+        constraint = *mint.owner == token_program.key(),
+        constraint = old_locker.mint == mint.key()
+    )]
+    mint: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [
+            mint.key().as_ref()
+        ],
+        bump = mint_info.bump
+    )]
+    mint_info: ProgramAccount<'info, MintInfo>,
+
+    #[account(
+        init,
+        payer = old_owner,
+        seeds = [
+            old_locker.key().as_ref(),
+            old_locker.current_unlock_date.to_be_bytes().as_ref(),
+            args.amount.to_be_bytes().as_ref()
+        ],
+        bump = args.locker_bump,
+        space = Locker::space(old_locker.schedule.len(), 0),
+    )]
+    new_locker: ProgramAccount<'info, Locker>,
+    new_owner: AccountInfo<'info>,
+    /// This authority allows the program to sign token transfer
+    /// back to target wallet.
+    #[account(
+        seeds = [
+            new_locker.key().as_ref()
+        ],
+        bump = args.vault_bump
+    )]
+    new_vault_authority: AccountInfo<'info>,
+    /// Associated token account of new vault authority.
+    #[account(
+        mut,
+        constraint = new_vault.key()
+            == token_interface::associated_token_address(
+                new_vault_authority.key,
+                mint.key,
+                token_program.key
+            )
+    )]
+    new_vault: AccountInfo<'info>,
+    config: ProgramAccount<'info, Config>,
+
+    clock: Sysvar<'info, Clock>,
+    #[account(
+        constraint = token_interface::is_token_program(token_program.key)
+    )]
+    token_program: AccountInfo<'info>,
+    system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MergeLockers<'info> {
+    #[account(mut)]
+    source_locker: ProgramAccount<'info, Locker>,
+    #[account(mut)]
+    destination_locker: ProgramAccount<'info, Locker>,
+    #[account(
+        signer,
+        constraint = source_locker.receipt_mint.is_some() || source_locker.owner == owner.key(),
+        constraint = destination_locker.receipt_mint.is_some()
+            || destination_locker.owner == owner.key()
+    )]
+    owner: AccountInfo<'info>,
+    /// This authority allows the program to sign token transfer
+    /// to destination vault.
+    source_vault_authority: AccountInfo<'info>,
+    #[account(
+        mut,
+        constraint = source_locker.vault == source_vault.key()
+    )]
+    source_vault: AccountInfo<'info>,
+    #[account(
+        mut,
+        constraint = destination_locker.vault == destination_vault.key()
+    )]
+    destination_vault: AccountInfo<'info>,
+    #[account(
+        constraint = *mint.owner == token_program.key(),
+        constraint = source_locker.mint == mint.key(),
+        constraint = destination_locker.mint == mint.key()
+    )]
+    mint: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [
+            mint.key().as_ref()
+        ],
+        bump = mint_info.bump
+    )]
+    mint_info: ProgramAccount<'info, MintInfo>,
+    config: ProgramAccount<'info, Config>,
+
+    #[account(
+        constraint = token_interface::is_token_program(token_program.key)
+    )]
+    token_program: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Revoke<'info> {
+    #[account(mut)]
+    locker: ProgramAccount<'info, Locker>,
+    #[account(
+        mut,
+        signer,
+        constraint = locker.revocable && locker.creator == creator.key()
+    )]
+    creator: AccountInfo<'info>,
+    /// This authority allows the program to sign token transfer
+    /// back to creator.
+    vault_authority: AccountInfo<'info>,
+    #[account(
+        mut,
+        constraint = locker.vault == vault.key()
+    )]
+    vault: AccountInfo<'info>,
+    #[account(
+        constraint = *mint.owner == token_program.key(),
+        constraint = locker.mint == mint.key()
+    )]
+    mint: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [
+            mint.key().as_ref()
+        ],
+        bump = mint_info.bump
+    )]
+    mint_info: ProgramAccount<'info, MintInfo>,
+    #[account(mut)]
+    target_wallet: AccountInfo<'info>,
+    config: ProgramAccount<'info, Config>,
+
+    clock: Sysvar<'info, Clock>,
+    #[account(
+        constraint = token_interface::is_token_program(token_program.key)
+    )]
+    token_program: AccountInfo<'info>,
+}
+
+/// For test purposes only!
+#[derive(Accounts)]
+pub struct CloseLocker<'info> {
+    #[account(mut)]
+    locker: ProgramAccount<'info, Locker>,
+    #[account(
+        signer,
+        constraint = locker.owner == owner.key()
+    )]
+    owner: AccountInfo<'info>,
+    /// This authority allows the program to sign token transfer
+    /// back to target wallet.
+    vault_authority: AccountInfo<'info>,
+    #[account(
+        mut,
+        constraint = locker.vault == vault.key()
+    )]
+    vault: AccountInfo<'info>,
+    #[account(
+        constraint = *mint.owner == token_program.key(),
+        constraint = locker.mint == mint.key()
+    )]
+    mint: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [
+            mint.key().as_ref()
+        ],
+        bump = mint_info.bump
+    )]
+    mint_info: ProgramAccount<'info, MintInfo>,
+    #[account(mut)]
+    target_wallet: AccountInfo<'info>,
+
+    #[account(
+        constraint = token_interface::is_token_program(token_program.key)
+    )]
+    token_program: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(bump: u8)]
+pub struct RequestEmergencyUnlock<'info> {
+    locker: ProgramAccount<'info, Locker>,
+    #[account(
+        mut,
+        signer,
+        constraint = locker.receipt_mint.is_some() || locker.owner == owner.key()
+    )]
+    owner: AccountInfo<'info>,
+    #[account(
+        init,
+        payer = owner,
+        seeds = [
+            "emergency_unlock".as_ref(),
+            locker.key().as_ref()
+        ],
+        bump = bump,
+        space = EmergencyUnlock::LEN
+    )]
+    emergency_unlock: ProgramAccount<'info, EmergencyUnlock>,
+    config: ProgramAccount<'info, Config>,
+
+    clock: Sysvar<'info, Clock>,
+    system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ApproveEmergencyUnlock<'info> {
+    #[account(signer)]
+    admin: AccountInfo<'info>,
+    #[account(
+        seeds = [
+            "config".as_ref()
+        ],
+        bump = config.bump,
+        constraint = config.admin == admin.key()
+    )]
+    config: ProgramAccount<'info, Config>,
+    #[account(mut)]
+    emergency_unlock: ProgramAccount<'info, EmergencyUnlock>,
+
+    clock: Sysvar<'info, Clock>,
 }
 
 #[derive(Accounts)]
-pub struct TransferOwnership<'info> {
-    #[account(mut)]
-    locker: ProgramAccount<'info, Locker>,
+pub struct CancelEmergencyUnlock<'info> {
     #[account(
         signer,
-        constraint = locker.owner == owner.key()
+        constraint = authority.key() == emergency_unlock.requester
+            || authority.key() == config.admin
+            || is_locker_owner(&locker, &authority.key())
     )]
-    owner: AccountInfo<'info>,
-    new_owner: AccountInfo<'info>,
-}
-
-#[derive(Accounts)]
-pub struct IncrementLock<'info> {
-    #[account(mut)]
-    locker: ProgramAccount<'info, Locker>,
+    authority: AccountInfo<'info>,
+    /// Request of the previous owner would block the new one otherwise.
     #[account(
-        mut,
-        constraint = vault.mint == funding_wallet.mint,
-        constraint = locker.vault == vault.key()
+        constraint = locker.key() == emergency_unlock.locker
     )]
-    vault: Account<'info, TokenAccount>,
+    locker: AccountInfo<'info>,
     #[account(
         seeds = [
-            vault.mint.key().as_ref()
+            "config".as_ref()
         ],
-        bump = mint_info.bump
+        bump = config.bump
     )]
-    mint_info: ProgramAccount<'info, MintInfo>,
-    #[account(signer)]
-    funding_wallet_authority: AccountInfo<'info>,
-    #[account(mut)]
-    funding_wallet: Account<'info, TokenAccount>,
-    #[account(mut)]
-    fee_wallet: Account<'info, TokenAccount>,
     config: ProgramAccount<'info, Config>,
-
-    token_program: Program<'info, Token>,
+    #[account(mut)]
+    emergency_unlock: ProgramAccount<'info, EmergencyUnlock>,
+    /// Gets the rent back.
+    #[account(
+        mut,
+        constraint = requester.key() == emergency_unlock.requester
+    )]
+    requester: AccountInfo<'info>,
 }
 
 #[derive(Accounts)]
-pub struct WithdrawFunds<'info> {
+pub struct EmergencyUnlockLocker<'info> {
     #[account(mut)]
     locker: ProgramAccount<'info, Locker>,
     #[account(
+        mut,
         signer,
-        constraint = locker.owner == owner.key()
+        constraint = owner.key() == emergency_unlock.requester,
+        constraint = locker.receipt_mint.is_some() || locker.owner == owner.key()
     )]
     owner: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [
+            "emergency_unlock".as_ref(),
+            locker.key().as_ref()
+        ],
+        bump = emergency_unlock.bump
+    )]
+    emergency_unlock: ProgramAccount<'info, EmergencyUnlock>,
     /// This authority allows the program to sign token transfer
     /// back to target wallet.
     vault_authority: AccountInfo<'info>,
     #[account(
         mut,
-        constraint = vault.owner == vault_authority.key()
+        constraint = locker.vault == vault.key()
+    )]
+    vault: AccountInfo<'info>,
+    #[account(
+        constraint = *mint.owner == token_program.key(),
+        constraint = locker.mint == mint.key()
     )]
-    vault: Account<'info, TokenAccount>,
+    mint: AccountInfo<'info>,
     #[account(
         mut,
-        constraint = target_wallet.mint == vault.mint
+        seeds = [
+            mint.key().as_ref()
+        ],
+        bump = mint_info.bump
     )]
-    target_wallet: Account<'info, TokenAccount>,
+    mint_info: ProgramAccount<'info, MintInfo>,
+    #[account(mut)]
+    target_wallet: AccountInfo<'info>,
+    config: ProgramAccount<'info, Config>,
 
     clock: Sysvar<'info, Clock>,
-    token_program: Program<'info, Token>,
+    #[account(
+        constraint = token_interface::is_token_program(token_program.key)
+    )]
+    token_program: AccountInfo<'info>,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
-pub struct SplitLockerArgs {
-    locker_bump: u8,
-    vault_bump: u8,
-    amount: u64,
+#[derive(Accounts)]
+pub struct MigrateConfig<'info> {
+    #[account(mut, signer)]
+    admin: AccountInfo<'info>,
+    #[account(mut)]
+    config: AccountInfo<'info>,
+
+    system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-#[instruction(args: SplitLockerArgs)]
-pub struct SplitLocker<'info> {
+pub struct MigrateMintInfo<'info> {
+    #[account(mut, signer)]
+    payer: AccountInfo<'info>,
     #[account(mut)]
-    old_locker: ProgramAccount<'info, Locker>,
-    #[account(
-        signer,
-        constraint = old_locker.owner == old_owner.key()
-    )]
-    old_owner: AccountInfo<'info>,
-    /// This authority allows the program to sign token transfer
-    /// back to target wallet.
-    old_vault_authority: AccountInfo<'info>,
-    #[account(
-        mut,
-        constraint = old_vault.owner == old_vault_authority.key()
-    )]
-    old_vault: Account<'info, TokenAccount>,
-
+    mint_info: AccountInfo<'info>,
     #[account(
-        init,
-        payer = old_owner,
-        seeds = [
-            old_locker.key().as_ref(),
-            old_locker.current_unlock_date.to_be_bytes().as_ref(),
-            args.amount.to_be_bytes().as_ref()
-        ],
-        bump = args.locker_bump,
-        space = Locker::LEN,
+        constraint = token_interface::is_token_program(mint.owner)
     )]
-    new_locker: ProgramAccount<'info, Locker>,
-    new_owner: AccountInfo<'info>,
-    /// This authority allows the program to sign token transfer
-    /// back to target wallet.
+    mint: AccountInfo<'info>,
     #[account(
         seeds = [
-            new_locker.key().as_ref()
+            "config".as_ref()
         ],
-        bump = args.vault_bump
-    )]
-    new_vault_authority: AccountInfo<'info>,
-    #[account(
-        mut,
-        constraint = new_vault.mint == old_vault.mint
+        bump = config.bump
     )]
-    new_vault: Account<'info, TokenAccount>,
+    config: ProgramAccount<'info, Config>,
 
-    token_program: Program<'info, Token>,
     system_program: Program<'info, System>,
 }
 
-/// For test purposes only!
 #[derive(Accounts)]
-pub struct CloseLocker<'info> {
+pub struct MigrateLocker<'info> {
     #[account(mut)]
-    locker: ProgramAccount<'info, Locker>,
-    #[account(
-        signer,
-        constraint = locker.owner == owner.key()
-    )]
+    locker: AccountInfo<'info>,
+    #[account(mut, signer)]
     owner: AccountInfo<'info>,
-    /// This authority allows the program to sign token transfer
-    /// back to target wallet.
-    vault_authority: AccountInfo<'info>,
+    vault: AccountInfo<'info>,
     #[account(
-        mut,
-        constraint = vault.owner == vault_authority.key()
+        constraint = token_interface::is_token_program(mint.owner)
     )]
-    vault: Account<'info, TokenAccount>,
+    mint: AccountInfo<'info>,
     #[account(
         mut,
-        constraint = target_wallet.mint == vault.mint
+        seeds = [
+            mint.key().as_ref()
+        ],
+        bump = mint_info.bump
     )]
-    target_wallet: Account<'info, TokenAccount>,
+    mint_info: ProgramAccount<'info, MintInfo>,
+
+    system_program: Program<'info, System>,
+}
+
+/// Grows the account to `len` bytes for the current layout, with payer
+/// topping up rent exemption, and writes the upgraded data over the legacy one.
+fn write_migrated<'info, T: AccountSerialize>(
+    account: &AccountInfo<'info>,
+    data: &T,
+    len: usize,
+    payer: &AccountInfo<'info>,
+    system_program: &Program<'info, System>,
+) -> Result<()> {
+    let lamports = Rent::get()?
+        .minimum_balance(len)
+        .saturating_sub(account.lamports());
+    if lamports > 0 {
+        solana_program::program::invoke(
+            &solana_program::system_instruction::transfer(payer.key, account.key, lamports),
+            &[
+                payer.to_account_info(),
+                account.to_account_info(),
+                system_program.to_account_info(),
+            ],
+        )?;
+    }
 
-    token_program: Program<'info, Token>,
+    account.realloc(len, false)?;
+    let mut buffer = account.try_borrow_mut_data()?;
+    buffer.fill(0);
+    let mut writer: &mut [u8] = &mut buffer;
+    data.try_serialize(&mut writer)?;
+
+    Ok(())
 }
 
 /// floor(a * b / denominator)
@@ -782,6 +3348,213 @@ where
         .and_then(|r| r.floor().checked_as::<u64>())
 }
 
+/// Tranches should go in chronological order with growing unlocked part
+/// and end with the whole amount unlocked at unlock date.
+fn validate_schedule(schedule: &[Tranche], unlock_date: i64) -> Result<()> {
+    require!(schedule.len() <= Locker::MAX_TRANCHES, InvalidSchedule);
+
+    for pair in schedule.windows(2) {
+        require!(
+            pair[0].date < pair[1].date && pair[0].bps < pair[1].bps,
+            InvalidSchedule
+        );
+    }
+
+    // non-empty schedule is checked by caller
+    let last = schedule.last().unwrap();
+    require!(last.date == unlock_date, InvalidSchedule);
+    require!(last.bps as u64 == BASIS_POINTS, InvalidSchedule);
+
+    Ok(())
+}
+
+/// Shares should go to distinct wallets and add up to the whole.
+fn validate_beneficiaries(beneficiaries: &[Share]) -> Result<()> {
+    require!(
+        beneficiaries.len() <= Locker::MAX_BENEFICIARIES,
+        InvalidBeneficiaries
+    );
+
+    let mut total_bps = 0;
+    for (i, share) in beneficiaries.iter().enumerate() {
+        require!(share.bps > 0, InvalidBeneficiaries);
+        require!(
+            beneficiaries[..i]
+                .iter()
+                .all(|other| other.owner != share.owner),
+            InvalidBeneficiaries
+        );
+        total_bps += share.bps as u64;
+    }
+    require!(total_bps == BASIS_POINTS, InvalidBeneficiaries);
+
+    Ok(())
+}
+
+/// Tiers should go from shorter to longer locks.
+fn validate_fee_tiers(fee_tiers: &[FeeTier]) -> Result<()> {
+    require!(fee_tiers.len() <= Config::MAX_FEE_TIERS, InvalidFeeTiers);
+
+    for tier in fee_tiers {
+        require!(tier.min_duration > 0, InvalidFeeTiers);
+        require!(tier.fee_in_token_denominator > 0, InvalidFeeTiers);
+        require!(
+            tier.fee_in_token_numerator <= tier.fee_in_token_denominator,
+            InvalidFeeTiers
+        );
+    }
+
+    for pair in fee_tiers.windows(2) {
+        require!(pair[0].min_duration < pair[1].min_duration, InvalidFeeTiers);
+    }
+
+    Ok(())
+}
+
+fn validate_token_fee(numerator: u64, denominator: u64) -> Result<()> {
+    require!(denominator > 0 && numerator <= denominator, InvalidTokenFee);
+
+    Ok(())
+}
+
+/// Only referrers registered by admin get paid, and parties of the lock
+/// can't get the referral part of their own fee.
+fn validate_referrer(
+    referrer: Option<Pubkey>,
+    parties: &[Pubkey],
+    remaining_accounts: &[AccountInfo],
+) -> Result<()> {
+    if let Some(referrer) = referrer {
+        require!(!parties.contains(&referrer), InvalidReferrer);
+        require!(
+            is_registered_referrer(remaining_accounts, &referrer),
+            InvalidReferrer
+        );
+    }
+
+    Ok(())
+}
+
+fn validate_fee_recipients(fee_recipients: &[FeeRecipient]) -> Result<()> {
+    require!(
+        fee_recipients.len() <= Config::MAX_FEE_RECIPIENTS,
+        InvalidFeeRecipients
+    );
+
+    let mut total_bps = 0;
+    for (i, recipient) in fee_recipients.iter().enumerate() {
+        require!(recipient.bps > 0, InvalidFeeRecipients);
+        require!(
+            fee_recipients[..i]
+                .iter()
+                .all(|other| other.wallet != recipient.wallet),
+            InvalidFeeRecipients
+        );
+        total_bps += recipient.bps as u64;
+    }
+    require!(total_bps <= BASIS_POINTS, InvalidFeeRecipients);
+
+    Ok(())
+}
+
+/// Finds fee exemption of the wallet among remaining accounts,
+/// no discount if there's none.
+fn fee_discount(remaining_accounts: &[AccountInfo], wallet: &Pubkey) -> u16 {
+    remaining_accounts
+        .iter()
+        .filter(|account| account.owner == &ID)
+        .filter_map(|account| {
+            let data = account.try_borrow_data().ok()?;
+            FeeExemption::try_deserialize(&mut &data[..]).ok()
+        })
+        .find(|fee_exemption| fee_exemption.wallet == *wallet)
+        .map_or(0, |fee_exemption| fee_exemption.discount_bps)
+}
+
+/// Lockers closed since are owned by nobody.
+fn is_locker_owner(locker: &AccountInfo, wallet: &Pubkey) -> bool {
+    if locker.owner != &ID {
+        return false;
+    }
+
+    locker
+        .try_borrow_data()
+        .ok()
+        .and_then(|data| Locker::try_deserialize(&mut &data[..]).ok())
+        .map_or(false, |account| account.owner == *wallet)
+}
+
+/// Looks for the referrer account of the wallet among remaining accounts.
+fn is_registered_referrer(remaining_accounts: &[AccountInfo], wallet: &Pubkey) -> bool {
+    remaining_accounts
+        .iter()
+        .filter(|account| account.owner == &ID)
+        .filter_map(|account| {
+            let data = account.try_borrow_data().ok()?;
+            Referrer::try_deserialize(&mut &data[..]).ok()
+        })
+        .any(|referrer| referrer.wallet == *wallet)
+}
+
+fn remaining_account<'a, 'info>(
+    remaining_accounts: &'a [AccountInfo<'info>],
+    key: &Pubkey,
+    error: ErrorCode,
+) -> Result<&'a AccountInfo<'info>> {
+    let account = remaining_accounts
+        .iter()
+        .find(|account| account.key == key)
+        .ok_or(error)?;
+
+    Ok(account)
+}
+
+/// Referrers and fee recipients get fees in tokens to their associated token accounts.
+fn remaining_token_account<'a, 'info>(
+    remaining_accounts: &'a [AccountInfo<'info>],
+    wallet: &Pubkey,
+    mint: &AccountInfo,
+    token_program: &AccountInfo,
+    error: ErrorCode,
+) -> Result<&'a AccountInfo<'info>> {
+    let associated_token_account =
+        token_interface::associated_token_address(wallet, mint.key, token_program.key);
+
+    remaining_account(remaining_accounts, &associated_token_account, error)
+}
+
+/// Part of the fee paid to the referrer, nothing if there's none.
+fn referral_fee(config: &Config, fee: u64, has_referrer: bool) -> Result<u64> {
+    if !has_referrer {
+        return Ok(0);
+    }
+
+    let referral_fee =
+        mul_div(fee, config.referral_bps, BASIS_POINTS).ok_or(ErrorCode::IntegerOverflow)?;
+
+    Ok(referral_fee)
+}
+
+/// Parts of the fee paid to fee recipients from config, in the same order.
+fn recipient_fees(config: &Config, fee: u64) -> Result<Vec<u64>> {
+    config
+        .fee_recipients
+        .iter()
+        .map(|recipient| {
+            mul_div(fee, recipient.bps, BASIS_POINTS)
+                .ok_or_else(|| ErrorCode::IntegerOverflow.into())
+        })
+        .collect()
+}
+
+/// Part of the fee left after applying the discount.
+fn discounted(fee: u64, discount_bps: u16) -> Result<u64> {
+    let fee = mul_div(fee, BASIS_POINTS - discount_bps as u64, BASIS_POINTS)
+        .ok_or(ErrorCode::IntegerOverflow)?;
+
+    Ok(fee)
+}
+
 fn should_pay_in_sol(config: &Config, mint_info: &MintInfo, fee_in_sol: bool) -> bool {
     match (
         config.mint_info_permissioned,
@@ -814,122 +3587,366 @@ fn should_pay_in_tokens(config: &Config, mint_info: &MintInfo, fee_in_sol: bool)
 
 struct FeeInSol<'pay, 'info> {
     fee_wallet: &'pay AccountInfo<'info>,
+    referrer: Option<&'pay AccountInfo<'info>>,
+    /// Should contain wallets of fee recipients and the price feed from config.
+    remaining_accounts: &'pay [AccountInfo<'info>],
     payer: &'pay AccountInfo<'info>,
     config: &'pay Config,
     mint_info: &'pay mut MintInfo,
+    now: i64,
+    lock_duration: i64,
+    discount_bps: u16,
     system_program: &'pay Program<'info, System>,
 }
 
-impl FeeInSol<'_, '_> {
+impl<'info> FeeInSol<'_, 'info> {
     fn pay(self) -> Result<()> {
         require!(
             self.fee_wallet.key() == self.config.fee_wallet,
             InvalidFeeWallet
         );
 
-        self.payer.key().log();
-        self.fee_wallet.key().log();
+        let fee_tier = self.config.fee_tier(self.lock_duration);
+        let fee = discounted(self.fee_in_lamports(&fee_tier)?, self.discount_bps)?;
+        let referral_fee = referral_fee(self.config, fee, self.referrer.is_some())?;
+        let recipient_fees = recipient_fees(self.config, fee - referral_fee)?;
+
+        for (recipient, &recipient_fee) in self.config.fee_recipients.iter().zip(&recipient_fees) {
+            let wallet = remaining_account(
+                self.remaining_accounts,
+                &recipient.wallet,
+                ErrorCode::InvalidFeeRecipient,
+            )?;
+            self.transfer(wallet, recipient_fee)?;
+        }
+        self.transfer(
+            self.fee_wallet,
+            fee - referral_fee - recipient_fees.iter().sum::<u64>(),
+        )?;
+        if let Some(referrer) = self.referrer {
+            self.transfer(referrer, referral_fee)?;
+        }
+
+        // if not permissioned we allow one-time fees,
+        // but only the full one whitelists the mint
+        if !self.config.mint_info_permissioned && self.discount_bps == 0 {
+            self.mint_info.fee_paid = true;
+        }
+
+        emit!(FeePaid {
+            payer: self.payer.key(),
+            fee_wallet: self.fee_wallet.key(),
+            mint: None,
+            amount: fee,
+            discount_bps: self.discount_bps,
+            referrer: self.referrer.map(|referrer| referrer.key()),
+            referral_amount: referral_fee,
+            recipient_amounts: recipient_fees,
+        });
+
+        Ok(())
+    }
+
+    /// Flat fee is priced in USD if there's a price feed in config.
+    fn fee_in_lamports(&self, fee_tier: &FeeTier) -> Result<u64> {
+        let feed = match self.config.sol_price_feed {
+            Some(feed) => feed,
+            None => return Ok(fee_tier.fee_in_lamports),
+        };
+
+        let account = remaining_account(
+            self.remaining_accounts,
+            &feed.account,
+            ErrorCode::InvalidPriceFeed,
+        )?;
+        let price = price_feed::Price::load(account)?;
+        price.require_fresh(self.now, feed.max_age, feed.max_confidence_bps)?;
+
+        price.cents_to_lamports(fee_tier.fee_in_usd_cents)
+    }
 
+    fn transfer(&self, to: &AccountInfo<'info>, lamports: u64) -> Result<()> {
         solana_program::program::invoke(
-            &solana_program::system_instruction::transfer(
-                self.payer.to_account_info().key,
-                self.fee_wallet.key,
-                self.config.fee_in_sol * solana_program::native_token::LAMPORTS_PER_SOL,
-            ),
+            &solana_program::system_instruction::transfer(self.payer.key, to.key, lamports),
             &[
                 self.payer.to_account_info(),
-                self.fee_wallet.to_account_info(),
+                to.to_account_info(),
                 self.system_program.to_account_info(),
             ],
         )?;
 
-        // if not permissioned we allow one-time fees
-        if !self.config.mint_info_permissioned {
-            self.mint_info.fee_paid = true;
-        }
-
         Ok(())
     }
 }
 
 struct FeeInTokens<'pay, 'info> {
     config: &'pay Config,
-    funding_wallet: &'pay mut Account<'info, TokenAccount>,
+    funding_wallet: &'pay AccountInfo<'info>,
     funding_wallet_authority: &'pay AccountInfo<'info>,
-    fee_wallet: &'pay Account<'info, TokenAccount>,
+    fee_wallet: &'pay AccountInfo<'info>,
+    /// Associated token account of the referrer.
+    referrer: Option<&'pay AccountInfo<'info>>,
+    /// Should contain associated token accounts of fee recipients.
+    remaining_accounts: &'pay [AccountInfo<'info>],
+    mint: &'pay AccountInfo<'info>,
+    decimals: u8,
     amount: u64,
-    token_program: &'pay Program<'info, Token>,
+    lock_duration: i64,
+    discount_bps: u16,
+    token_program: &'pay AccountInfo<'info>,
 }
 
-impl FeeInTokens<'_, '_> {
+impl<'info> FeeInTokens<'_, 'info> {
     fn pay(self) -> Result<u64> {
-        let associated_token_account =
-            get_associated_token_address(&self.config.fee_wallet, &self.funding_wallet.mint);
+        self.require_fee_wallet()?;
+
+        let fee_tier = self.config.fee_tier(self.lock_duration);
+        let lock_fee = mul_div(
+            self.amount,
+            fee_tier.fee_in_token_numerator,
+            fee_tier.fee_in_token_denominator,
+        )
+        .ok_or(ErrorCode::IntegerOverflow)?;
+        let lock_fee = discounted(lock_fee, self.discount_bps)?;
+        let (referral_fee, recipient_fees) = self.distribute(lock_fee)?;
+
+        emit!(FeePaid {
+            payer: self.funding_wallet_authority.key(),
+            fee_wallet: self.fee_wallet.key(),
+            mint: Some(self.mint.key()),
+            amount: lock_fee,
+            discount_bps: self.discount_bps,
+            referrer: self.referrer.map(|referrer| referrer.key()),
+            referral_amount: referral_fee,
+            recipient_amounts: recipient_fees,
+        });
+
+        Ok(lock_fee)
+    }
+
+    /// Pays the flat fee in stable token, the alternative to `FeeInSol`.
+    fn pay_flat(self, mint_info: &mut MintInfo) -> Result<()> {
+        self.require_fee_wallet()?;
+
+        let fee_tier = self.config.fee_tier(self.lock_duration);
+        let fee = discounted(fee_tier.fee_in_stable, self.discount_bps)?;
+        let (referral_fee, recipient_fees) = self.distribute(fee)?;
+
+        // whitelists the mint the same way the fee in SOL does
+        if !self.config.mint_info_permissioned && self.discount_bps == 0 {
+            mint_info.fee_paid = true;
+        }
+
+        emit!(FeePaid {
+            payer: self.funding_wallet_authority.key(),
+            fee_wallet: self.fee_wallet.key(),
+            mint: Some(self.mint.key()),
+            amount: fee,
+            discount_bps: self.discount_bps,
+            referrer: self.referrer.map(|referrer| referrer.key()),
+            referral_amount: referral_fee,
+            recipient_amounts: recipient_fees,
+        });
+
+        Ok(())
+    }
+
+    fn require_fee_wallet(&self) -> Result<()> {
+        let associated_token_account = token_interface::associated_token_address(
+            &self.config.fee_wallet,
+            self.mint.key,
+            self.token_program.key,
+        );
 
         require!(
             associated_token_account == self.fee_wallet.key(),
             InvalidFeeWallet
         );
 
-        let lock_fee = mul_div(
-            self.amount,
-            self.config.fee_in_token_numerator,
-            self.config.fee_in_token_denominator,
-        )
-        .ok_or(ErrorCode::IntegerOverflow)?;
+        Ok(())
+    }
+
+    /// Splits the fee between the referrer, fee recipients and fee wallet,
+    /// returns parts of the referrer and of fee recipients.
+    fn distribute(&self, fee: u64) -> Result<(u64, Vec<u64>)> {
+        let referral_fee = referral_fee(self.config, fee, self.referrer.is_some())?;
+        let recipient_fees = recipient_fees(self.config, fee - referral_fee)?;
+
+        for (recipient, &recipient_fee) in self.config.fee_recipients.iter().zip(&recipient_fees) {
+            let token_account = remaining_token_account(
+                self.remaining_accounts,
+                &recipient.wallet,
+                self.mint,
+                self.token_program,
+                ErrorCode::InvalidFeeRecipient,
+            )?;
+            self.transfer(token_account, recipient_fee)?;
+        }
+        self.transfer(
+            self.fee_wallet,
+            fee - referral_fee - recipient_fees.iter().sum::<u64>(),
+        )?;
+        if let Some(referrer) = self.referrer {
+            self.transfer(referrer, referral_fee)?;
+        }
+
+        Ok((referral_fee, recipient_fees))
+    }
 
+    fn transfer(&self, to: &AccountInfo<'info>, amount: u64) -> Result<()> {
         TokenTransfer {
-            amount: lock_fee,
+            amount,
             from: self.funding_wallet,
-            to: self.fee_wallet,
+            to,
+            mint: self.mint,
+            decimals: self.decimals,
             authority: self.funding_wallet_authority,
             token_program: self.token_program,
             signers: None,
         }
-        .make()?;
+        .make()
+    }
+}
 
-        sol_log_64(self.amount, lock_fee, self.amount - lock_fee, 0, 0);
+/// Flat fee in stable token is paid from the payer's associated token account,
+/// all the accounts involved are found among remaining accounts.
+fn stable_fee<'pay, 'info>(
+    config: &'pay Config,
+    payer: &'pay AccountInfo<'info>,
+    referrer: Option<&Pubkey>,
+    remaining_accounts: &'pay [AccountInfo<'info>],
+    lock_duration: i64,
+    discount_bps: u16,
+) -> Result<FeeInTokens<'pay, 'info>> {
+    let mint = config.stable_fee_mint.ok_or(ErrorCode::StableFeeDisabled)?;
+    let mint = remaining_account(
+        remaining_accounts,
+        &mint,
+        ErrorCode::InvalidStableFeeAccount,
+    )?;
+    require!(
+        token_interface::is_token_program(mint.owner),
+        InvalidStableFeeAccount
+    );
+    let token_program = remaining_account(
+        remaining_accounts,
+        mint.owner,
+        ErrorCode::InvalidStableFeeAccount,
+    )?;
+    let decimals = token_interface::Mint::load(mint)?.decimals;
+
+    Ok(FeeInTokens {
+        config,
+        funding_wallet: remaining_token_account(
+            remaining_accounts,
+            payer.key,
+            mint,
+            token_program,
+            ErrorCode::InvalidStableFeeAccount,
+        )?,
+        funding_wallet_authority: payer,
+        fee_wallet: remaining_token_account(
+            remaining_accounts,
+            &config.fee_wallet,
+            mint,
+            token_program,
+            ErrorCode::InvalidFeeWallet,
+        )?,
+        referrer: referrer
+            .map(|referrer| {
+                remaining_token_account(
+                    remaining_accounts,
+                    referrer,
+                    mint,
+                    token_program,
+                    ErrorCode::InvalidReferrer,
+                )
+            })
+            .transpose()?,
+        remaining_accounts,
+        mint,
+        decimals,
+        amount: 0,
+        lock_duration,
+        discount_bps,
+        token_program,
+    })
+}
 
-        Ok(lock_fee)
+struct Receipt<'pay, 'info> {
+    mint: &'pay AccountInfo<'info>,
+    account: &'pay AccountInfo<'info>,
+    owner: &'pay Pubkey,
+    vault_authority: &'pay AccountInfo<'info>,
+    token_program: &'pay AccountInfo<'info>,
+    signers: &'pay [&'pay [&'pay [u8]]],
+}
+
+impl Receipt<'_, '_> {
+    /// Mints the only receipt token to the owner. The mint should be created
+    /// beforehand with vault authority as mint authority and no supply.
+    fn issue(self) -> Result<()> {
+        let mint = token_interface::Mint::load(self.mint)?;
+        mint.require_lockable()?;
+        require!(
+            *self.mint.owner == self.token_program.key()
+                && mint.decimals == 0
+                && mint.supply == 0
+                && mint.mint_authority == Some(self.vault_authority.key())
+                && mint.freeze_authority.is_none(),
+            InvalidReceipt
+        );
+
+        let account = token_interface::TokenAccount::load(self.account)?;
+        require!(
+            account.mint == self.mint.key() && account.owner == *self.owner,
+            InvalidReceipt
+        );
+
+        token_interface::mint_to(
+            self.token_program,
+            self.mint,
+            self.account,
+            self.vault_authority,
+            1,
+            self.signers,
+        )?;
+        token_interface::disable_minting(
+            self.token_program,
+            self.mint,
+            self.vault_authority,
+            self.signers,
+        )
     }
 }
 
 struct TokenTransfer<'pay, 'info> {
     amount: u64,
-    from: &'pay mut Account<'info, TokenAccount>,
-    to: &'pay Account<'info, TokenAccount>,
+    from: &'pay AccountInfo<'info>,
+    to: &'pay AccountInfo<'info>,
+    mint: &'pay AccountInfo<'info>,
+    decimals: u8,
     authority: &'pay AccountInfo<'info>,
-    token_program: &'pay Program<'info, Token>,
+    token_program: &'pay AccountInfo<'info>,
     signers: Option<&'pay [&'pay [&'pay [u8]]]>,
 }
 
 impl TokenTransfer<'_, '_> {
     fn make(self) -> Result<()> {
-        let amount_before = self.from.amount;
-
-        self.from.key().log();
-        self.to.key().log();
-        self.authority.key().log();
-
-        let cpi_ctx = CpiContext::new(
-            self.token_program.to_account_info(),
-            Transfer {
-                from: self.from.to_account_info(),
-                to: self.to.to_account_info(),
-                authority: self.authority.to_account_info(),
-            },
-        );
-        let cpi_ctx = match self.signers {
-            Some(signers) => cpi_ctx.with_signer(signers),
-            None => cpi_ctx,
-        };
-
-        token::transfer(cpi_ctx, self.amount)?;
-
-        self.from.reload()?;
-        let amount_after = self.from.amount;
+        let amount_before = token_interface::TokenAccount::load(self.from)?.amount;
+
+        token_interface::transfer_checked(
+            self.token_program,
+            self.from,
+            self.mint,
+            self.to,
+            self.authority,
+            self.amount,
+            self.decimals,
+            self.signers.unwrap_or(&[]),
+        )?;
 
-        sol_log_64(amount_before, amount_after, self.amount, 0, 0);
+        let amount_after = token_interface::TokenAccount::load(self.from)?.amount;
 
         require!(
             amount_before - amount_after == self.amount,
@@ -0,0 +1,100 @@
+//! Minimal reader of Pyth price accounts.
+//!
+//! Only the aggregate price of the version 2 price account is read, by its
+//! offsets, to convert fees set in USD into lamports.
+
+use std::convert::TryFrom;
+
+use anchor_lang::prelude::*;
+
+use crate::{ErrorCode, Result};
+
+/// Price account header up to the end of the aggregate price.
+const PRICE_ACCOUNT_LEN: usize = 240;
+
+const MAGIC: u32 = 0xa1b2c3d4;
+const VERSION: u32 = 2;
+const ACCOUNT_TYPE_PRICE: u32 = 3;
+const PRICE_STATUS_TRADING: u32 = 1;
+
+/// Lamports worth a USD cent if SOL costs 1 USD.
+const LAMPORTS_PER_CENT: u128 = 10_000_000;
+
+pub struct Price {
+    price: i64,
+    conf: u64,
+    expo: i32,
+    timestamp: i64,
+}
+
+impl Price {
+    pub fn load(info: &AccountInfo) -> Result<Self> {
+        let data = info.try_borrow_data()?;
+        require!(data.len() >= PRICE_ACCOUNT_LEN, InvalidPriceFeed);
+        require!(
+            read_u32(&data, 0) == MAGIC
+                && read_u32(&data, 4) == VERSION
+                && read_u32(&data, 8) == ACCOUNT_TYPE_PRICE,
+            InvalidPriceFeed
+        );
+        // publishers don't agree on the price if it's not trading
+        require!(read_u32(&data, 224) == PRICE_STATUS_TRADING, StalePrice);
+
+        let price = Self {
+            price: read_u64(&data, 208) as i64,
+            conf: read_u64(&data, 216),
+            expo: read_u32(&data, 20) as i32,
+            timestamp: read_u64(&data, 96) as i64,
+        };
+        require!(price.price > 0, InvalidPriceFeed);
+
+        Ok(price)
+    }
+
+    /// Fails if the price is older than `max_age` seconds or its confidence
+    /// interval is wider than `max_confidence_bps` of the price.
+    pub fn require_fresh(&self, now: i64, max_age: i64, max_confidence_bps: u16) -> Result<()> {
+        require!(now.saturating_sub(self.timestamp) <= max_age, StalePrice);
+        require!(
+            self.conf as u128 * crate::BASIS_POINTS as u128
+                <= self.price as u128 * max_confidence_bps as u128,
+            PriceTooUncertain
+        );
+
+        Ok(())
+    }
+
+    /// Lamports worth `cents` USD cents of SOL at this price.
+    pub fn cents_to_lamports(&self, cents: u64) -> Result<u64> {
+        let scale = 10u128
+            .checked_pow(self.expo.unsigned_abs())
+            .ok_or(ErrorCode::IntegerOverflow)?;
+        let (numerator, denominator) = if self.expo < 0 {
+            (cents as u128 * LAMPORTS_PER_CENT)
+                .checked_mul(scale)
+                .map(|numerator| (numerator, self.price as u128))
+        } else {
+            (self.price as u128)
+                .checked_mul(scale)
+                .map(|denominator| (cents as u128 * LAMPORTS_PER_CENT, denominator))
+        }
+        .ok_or(ErrorCode::IntegerOverflow)?;
+
+        let lamports =
+            u64::try_from(numerator / denominator).map_err(|_| ErrorCode::IntegerOverflow)?;
+
+        Ok(lamports)
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&data[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&data[offset..offset + 8]);
+    u64::from_le_bytes(bytes)
+}
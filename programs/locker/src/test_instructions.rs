@@ -0,0 +1,64 @@
+//! Instructions for tests only, they ignore the unlock dates.
+//!
+//! Their logic is built only with `test-instructions` feature. Anchor can't
+//! leave an instruction out of the program, so without the feature the
+//! instruction is still there but always fails.
+
+use anchor_lang::prelude::*;
+
+use crate::{CloseLocker, Result};
+
+#[cfg(feature = "test-instructions")]
+pub fn close_locker(ctx: Context<CloseLocker>) -> Result<()> {
+    use anchor_lang::AccountsClose;
+
+    use crate::{token_interface, LockerClosed, TokenTransfer};
+
+    let locker = &ctx.accounts.locker;
+    ctx.accounts.mint_info.remove_locker(locker);
+    let mint = token_interface::Mint::load(&ctx.accounts.mint)?;
+
+    let vault = token_interface::TokenAccount::load(&ctx.accounts.vault)?;
+    require!(
+        vault.owner == ctx.accounts.vault_authority.key(),
+        InvalidTokenAccount
+    );
+
+    let locker_key = locker.key();
+    let seeds = &[locker_key.as_ref(), &[locker.vault_bump]];
+    let signers = &[&seeds[..]];
+
+    TokenTransfer {
+        amount: vault.amount,
+        from: &ctx.accounts.vault,
+        to: &ctx.accounts.target_wallet,
+        mint: &ctx.accounts.mint,
+        decimals: mint.decimals,
+        authority: &ctx.accounts.vault_authority,
+        token_program: &ctx.accounts.token_program,
+        signers: Some(signers),
+    }
+    .make()?;
+
+    token_interface::close_account(
+        &ctx.accounts.token_program,
+        &ctx.accounts.vault,
+        &ctx.accounts.owner,
+        &ctx.accounts.vault_authority,
+        signers,
+    )?;
+
+    locker.close(ctx.accounts.owner.to_account_info())?;
+
+    emit!(LockerClosed {
+        locker: locker_key,
+        owner: locker.owner,
+    });
+
+    Ok(())
+}
+
+#[cfg(not(feature = "test-instructions"))]
+pub fn close_locker(_ctx: Context<CloseLocker>) -> Result<()> {
+    Err(crate::ErrorCode::TestInstructionsDisabled.into())
+}
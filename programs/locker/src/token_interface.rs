@@ -0,0 +1,320 @@
+//! Minimal interface over the SPL Token and Token-2022 programs.
+//!
+//! anchor-spl only knows about the original token program, so accounts of
+//! both programs are parsed here by hand and instructions are built directly.
+//! Only the base layouts and mint extensions the locker cares about are read.
+
+use anchor_lang::{
+    prelude::*,
+    solana_program::{
+        self,
+        instruction::{AccountMeta, Instruction},
+    },
+};
+use anchor_spl::{associated_token, token};
+
+use crate::{ErrorCode, Result};
+
+pub mod token_2022 {
+    anchor_lang::declare_id!("TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb");
+}
+
+const TOKEN_ACCOUNT_LEN: usize = 165;
+const MINT_LEN: usize = 82;
+
+/// Token-2022 accounts with extensions store the account type right after
+/// the base token account layout, followed by extensions in TLV format.
+const ACCOUNT_TYPE_OFFSET: usize = TOKEN_ACCOUNT_LEN;
+const ACCOUNT_TYPE_MINT: u8 = 1;
+const ACCOUNT_TYPE_ACCOUNT: u8 = 2;
+
+const EXTENSION_UNINITIALIZED: u16 = 0;
+const EXTENSION_TRANSFER_FEE_CONFIG: u16 = 1;
+const EXTENSION_DEFAULT_ACCOUNT_STATE: u16 = 6;
+const EXTENSION_NON_TRANSFERABLE: u16 = 9;
+const EXTENSION_PERMANENT_DELEGATE: u16 = 12;
+const EXTENSION_TRANSFER_HOOK: u16 = 14;
+const EXTENSION_PAUSABLE: u16 = 26;
+
+const ACCOUNT_STATE_UNINITIALIZED: u8 = 0;
+const ACCOUNT_STATE_FROZEN: u8 = 2;
+
+const INSTRUCTION_SET_AUTHORITY: u8 = 6;
+const INSTRUCTION_MINT_TO: u8 = 7;
+const INSTRUCTION_CLOSE_ACCOUNT: u8 = 9;
+const INSTRUCTION_TRANSFER_CHECKED: u8 = 12;
+
+const AUTHORITY_TYPE_MINT_TOKENS: u8 = 0;
+
+pub fn is_token_program(key: &Pubkey) -> bool {
+    *key == token::ID || *key == token_2022::ID
+}
+
+/// Same as `get_associated_token_address` but for any token program.
+pub fn associated_token_address(wallet: &Pubkey, mint: &Pubkey, token_program: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[wallet.as_ref(), token_program.as_ref(), mint.as_ref()],
+        &associated_token::ID,
+    )
+    .0
+}
+
+pub struct TokenAccount {
+    pub mint: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+}
+
+impl TokenAccount {
+    pub fn load(info: &AccountInfo) -> Result<Self> {
+        require!(is_token_program(info.owner), InvalidTokenAccount);
+
+        let data = info.try_borrow_data()?;
+        // only Token-2022 accounts may be longer than the base layout
+        let extended = data.len() > ACCOUNT_TYPE_OFFSET && *info.owner == token_2022::ID;
+        require!(
+            data.len() == TOKEN_ACCOUNT_LEN || extended,
+            InvalidTokenAccount
+        );
+        if extended {
+            require!(
+                data[ACCOUNT_TYPE_OFFSET] == ACCOUNT_TYPE_ACCOUNT,
+                InvalidTokenAccount
+            );
+        }
+        require!(
+            data[108] != ACCOUNT_STATE_UNINITIALIZED,
+            InvalidTokenAccount
+        );
+
+        let mut amount = [0; 8];
+        amount.copy_from_slice(&data[64..72]);
+
+        Ok(Self {
+            mint: Pubkey::new(&data[0..32]),
+            owner: Pubkey::new(&data[32..64]),
+            amount: u64::from_le_bytes(amount),
+        })
+    }
+}
+
+pub struct Mint {
+    pub mint_authority: Option<Pubkey>,
+    pub supply: u64,
+    pub decimals: u8,
+    pub freeze_authority: Option<Pubkey>,
+    /// Set when the mint has an extension which lets someone else move,
+    /// freeze or tax locked tokens, or makes them impossible to withdraw.
+    unsafe_extension: bool,
+}
+
+impl Mint {
+    pub fn load(info: &AccountInfo) -> Result<Self> {
+        require!(is_token_program(info.owner), InvalidMint);
+
+        let data = info.try_borrow_data()?;
+        // extended mints are padded to the token account length, so a mint
+        // can't be mistaken for a token account and the other way around
+        let extended = data.len() > ACCOUNT_TYPE_OFFSET && *info.owner == token_2022::ID;
+        require!(data.len() == MINT_LEN || extended, InvalidMint);
+        // is_initialized flag
+        require!(data[45] == 1, InvalidMint);
+
+        let unsafe_extension = if extended {
+            require!(data[ACCOUNT_TYPE_OFFSET] == ACCOUNT_TYPE_MINT, InvalidMint);
+            has_unsafe_extension(&data[ACCOUNT_TYPE_OFFSET + 1..])?
+        } else {
+            false
+        };
+
+        let mut supply = [0; 8];
+        supply.copy_from_slice(&data[36..44]);
+
+        Ok(Self {
+            mint_authority: unpack_optional_pubkey(&data[0..36]),
+            supply: u64::from_le_bytes(supply),
+            decimals: data[44],
+            freeze_authority: unpack_optional_pubkey(&data[46..82]),
+            unsafe_extension,
+        })
+    }
+
+    /// New tokens can be locked only if nobody but the vault authority
+    /// is able to move them out of the vault.
+    pub fn require_lockable(&self) -> Result<()> {
+        require!(!self.unsafe_extension, UnsupportedMintExtension);
+        Ok(())
+    }
+}
+
+/// `COption<Pubkey>` is stored as a 4 byte tag followed by the key.
+fn unpack_optional_pubkey(data: &[u8]) -> Option<Pubkey> {
+    if data[0..4] == [0; 4] {
+        None
+    } else {
+        Some(Pubkey::new(&data[4..36]))
+    }
+}
+
+fn has_unsafe_extension(mut tlv: &[u8]) -> Result<bool> {
+    while tlv.len() >= 4 {
+        let extension_type = u16::from_le_bytes([tlv[0], tlv[1]]);
+        let length = u16::from_le_bytes([tlv[2], tlv[3]]) as usize;
+        let value = tlv.get(4..4 + length).ok_or(ErrorCode::InvalidMint)?;
+
+        match extension_type {
+            // the rest of the account is not used
+            EXTENSION_UNINITIALIZED => break,
+            // transfer fees would make the vault receive less than was deposited,
+            // a hook program set later would need accounts transfers don't pass
+            // and a paused mint can't be withdrawn; mint close authority is fine
+            // since the mint can't be closed while tokens are locked
+            EXTENSION_PERMANENT_DELEGATE
+            | EXTENSION_NON_TRANSFERABLE
+            | EXTENSION_TRANSFER_FEE_CONFIG
+            | EXTENSION_TRANSFER_HOOK
+            | EXTENSION_PAUSABLE => return Ok(true),
+            EXTENSION_DEFAULT_ACCOUNT_STATE if value.first() == Some(&ACCOUNT_STATE_FROZEN) => {
+                return Ok(true)
+            }
+            _ => {}
+        }
+
+        tlv = &tlv[4 + length..];
+    }
+
+    Ok(false)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn transfer_checked<'info>(
+    token_program: &AccountInfo<'info>,
+    from: &AccountInfo<'info>,
+    mint: &AccountInfo<'info>,
+    to: &AccountInfo<'info>,
+    authority: &AccountInfo<'info>,
+    amount: u64,
+    decimals: u8,
+    signers: &[&[&[u8]]],
+) -> Result<()> {
+    let mut data = vec![INSTRUCTION_TRANSFER_CHECKED];
+    data.extend_from_slice(&amount.to_le_bytes());
+    data.push(decimals);
+
+    let instruction = Instruction {
+        program_id: token_program.key(),
+        accounts: vec![
+            AccountMeta::new(from.key(), false),
+            AccountMeta::new_readonly(mint.key(), false),
+            AccountMeta::new(to.key(), false),
+            AccountMeta::new_readonly(authority.key(), true),
+        ],
+        data,
+    };
+
+    solana_program::program::invoke_signed(
+        &instruction,
+        &[
+            from.clone(),
+            mint.clone(),
+            to.clone(),
+            authority.clone(),
+            token_program.clone(),
+        ],
+        signers,
+    )?;
+
+    Ok(())
+}
+
+pub fn close_account<'info>(
+    token_program: &AccountInfo<'info>,
+    account: &AccountInfo<'info>,
+    destination: &AccountInfo<'info>,
+    authority: &AccountInfo<'info>,
+    signers: &[&[&[u8]]],
+) -> Result<()> {
+    let instruction = Instruction {
+        program_id: token_program.key(),
+        accounts: vec![
+            AccountMeta::new(account.key(), false),
+            AccountMeta::new(destination.key(), false),
+            AccountMeta::new_readonly(authority.key(), true),
+        ],
+        data: vec![INSTRUCTION_CLOSE_ACCOUNT],
+    };
+
+    solana_program::program::invoke_signed(
+        &instruction,
+        &[
+            account.clone(),
+            destination.clone(),
+            authority.clone(),
+            token_program.clone(),
+        ],
+        signers,
+    )?;
+
+    Ok(())
+}
+
+pub fn mint_to<'info>(
+    token_program: &AccountInfo<'info>,
+    mint: &AccountInfo<'info>,
+    to: &AccountInfo<'info>,
+    authority: &AccountInfo<'info>,
+    amount: u64,
+    signers: &[&[&[u8]]],
+) -> Result<()> {
+    let mut data = vec![INSTRUCTION_MINT_TO];
+    data.extend_from_slice(&amount.to_le_bytes());
+
+    let instruction = Instruction {
+        program_id: token_program.key(),
+        accounts: vec![
+            AccountMeta::new(mint.key(), false),
+            AccountMeta::new(to.key(), false),
+            AccountMeta::new_readonly(authority.key(), true),
+        ],
+        data,
+    };
+
+    solana_program::program::invoke_signed(
+        &instruction,
+        &[
+            mint.clone(),
+            to.clone(),
+            authority.clone(),
+            token_program.clone(),
+        ],
+        signers,
+    )?;
+
+    Ok(())
+}
+
+/// Removes mint authority, so no more tokens can ever be minted.
+pub fn disable_minting<'info>(
+    token_program: &AccountInfo<'info>,
+    mint: &AccountInfo<'info>,
+    authority: &AccountInfo<'info>,
+    signers: &[&[&[u8]]],
+) -> Result<()> {
+    let instruction = Instruction {
+        program_id: token_program.key(),
+        accounts: vec![
+            AccountMeta::new(mint.key(), false),
+            AccountMeta::new_readonly(authority.key(), true),
+        ],
+        // no new authority
+        data: vec![INSTRUCTION_SET_AUTHORITY, AUTHORITY_TYPE_MINT_TOKENS, 0],
+    };
+
+    solana_program::program::invoke_signed(
+        &instruction,
+        &[mint.clone(), authority.clone(), token_program.clone()],
+        signers,
+    )?;
+
+    Ok(())
+}
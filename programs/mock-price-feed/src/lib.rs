@@ -0,0 +1,83 @@
+//! Writes price accounts in the Pyth layout read by the locker, so tests
+//! on localnet can price fees without a real oracle. Its only instruction
+//! works in builds with `test-instructions` feature, never deploy those
+//! anywhere else.
+
+use anchor_lang::prelude::*;
+
+declare_id!("3SoGRd7SThK4aZziAygFABZBeCwYitf5L9W1LhKsHV6y");
+
+/// Size of a real Pyth price account.
+pub const PRICE_ACCOUNT_LEN: usize = 3312;
+
+#[cfg(feature = "test-instructions")]
+const MAGIC: u32 = 0xa1b2c3d4;
+#[cfg(feature = "test-instructions")]
+const VERSION: u32 = 2;
+#[cfg(feature = "test-instructions")]
+const ACCOUNT_TYPE_PRICE: u32 = 3;
+
+#[program]
+pub mod mock_price_feed {
+    use super::*;
+
+    /// Sets aggregate price of the account as if it was published now.
+    pub fn write_price(
+        ctx: Context<WritePrice>,
+        price: i64,
+        conf: u64,
+        expo: i32,
+        status: u32,
+    ) -> Result<()> {
+        write(ctx, price, conf, expo, status)
+    }
+}
+
+#[cfg(feature = "test-instructions")]
+fn write(ctx: Context<WritePrice>, price: i64, conf: u64, expo: i32, status: u32) -> Result<()> {
+    let mut data = ctx.accounts.price_feed.try_borrow_mut_data()?;
+
+    data[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+    data[4..8].copy_from_slice(&VERSION.to_le_bytes());
+    data[8..12].copy_from_slice(&ACCOUNT_TYPE_PRICE.to_le_bytes());
+    data[12..16].copy_from_slice(&(PRICE_ACCOUNT_LEN as u32).to_le_bytes());
+    data[20..24].copy_from_slice(&expo.to_le_bytes());
+    data[96..104].copy_from_slice(&ctx.accounts.clock.unix_timestamp.to_le_bytes());
+    data[208..216].copy_from_slice(&price.to_le_bytes());
+    data[216..224].copy_from_slice(&conf.to_le_bytes());
+    data[224..228].copy_from_slice(&status.to_le_bytes());
+    data[232..240].copy_from_slice(&ctx.accounts.clock.slot.to_le_bytes());
+
+    Ok(())
+}
+
+#[cfg(not(feature = "test-instructions"))]
+fn write(
+    _ctx: Context<WritePrice>,
+    _price: i64,
+    _conf: u64,
+    _expo: i32,
+    _status: u32,
+) -> Result<()> {
+    Err(ErrorCode::TestInstructionsDisabled.into())
+}
+
+#[derive(Accounts)]
+pub struct WritePrice<'info> {
+    /// Should be created with this program as owner, only the holder
+    /// of its keypair can write the price.
+    #[account(
+        mut,
+        signer,
+        constraint = price_feed.data_len() == PRICE_ACCOUNT_LEN
+    )]
+    price_feed: AccountInfo<'info>,
+
+    clock: Sysvar<'info, Clock>,
+}
+
+#[error]
+pub enum ErrorCode {
+    #[msg("The instruction is available only in test builds")]
+    TestInstructionsDisabled,
+}
@@ -2,9 +2,11 @@ import * as anchor from '@project-serum/anchor';
 import * as spl from "@solana/spl-token";
 import * as serumCmn from "@project-serum/common";
 import * as assert from 'assert';
+import * as fs from 'fs';
 
 import { Locker } from '../target/types/locker';
 import { CountryList } from '../target/types/country_list';
+import { MockPriceFeed } from '../target/types/mock_price_feed';
 import lockerClient from "../web3/locker/index";
 
 async function createMint(provider: anchor.Provider, authority?: anchor.web3.PublicKey) {
@@ -65,7 +67,7 @@ describe('locker', () => {
 
     await program.rpc.initConfig(
       {
-        feeInSol: new anchor.BN(1),
+        feeInLamports: new anchor.BN(anchor.web3.LAMPORTS_PER_SOL),
         feeInTokenNumerator: new anchor.BN(35),
         feeInTokenDenominator: new anchor.BN(10000),
         mintInfoPermissioned: false,
@@ -117,6 +119,51 @@ describe('locker', () => {
     assert.ok(vaultAccount.amount.eqn(10000));
   });
 
+  it('Hands over the admin role', async () => {
+    const [config, _bump] = await client.findConfigAddress();
+
+    await program.rpc.proposeAdmin({
+      accounts: {
+        admin: provider.wallet.publicKey,
+        config,
+        newAdmin: newOwner.publicKey,
+      }
+    });
+
+    let configAccount = await program.account.config.fetch(config);
+    assert.ok(configAccount.pendingAdmin.equals(newOwner.publicKey));
+
+    await program.rpc.acceptAdmin({
+      accounts: {
+        newAdmin: newOwner.publicKey,
+        config,
+      },
+      signers: [newOwner],
+    });
+
+    configAccount = await program.account.config.fetch(config);
+    assert.ok(configAccount.admin.equals(newOwner.publicKey));
+    assert.deepStrictEqual(configAccount.pendingAdmin, null);
+
+    await program.rpc.proposeAdmin({
+      accounts: {
+        admin: newOwner.publicKey,
+        config,
+        newAdmin: provider.wallet.publicKey,
+      },
+      signers: [newOwner],
+    });
+    await program.rpc.acceptAdmin({
+      accounts: {
+        newAdmin: provider.wallet.publicKey,
+        config,
+      }
+    });
+
+    configAccount = await program.account.config.fetch(config);
+    assert.ok(configAccount.admin.equals(provider.wallet.publicKey));
+  });
+
   it('Fails to withdraw funds if it is too early', async () => {
     const lockers = await program.account.locker.all();
     const lockerAccount = lockers[0];
@@ -140,6 +187,13 @@ describe('locker', () => {
 
     const newUnlockDate = unlockDate.addn(1);
 
+    let listener = null;
+    const event: any = new Promise((resolve, _reject) => {
+      listener = program.addEventListener("Relocked", (event, _slot) => {
+        resolve(event);
+      });
+    });
+
     await client.relock({
       unlockDate: newUnlockDate,
       locker: lockerAccountBefore,
@@ -147,6 +201,12 @@ describe('locker', () => {
 
     const lockerAccountAfter = await program.account.locker.fetch(lockerAccountBefore.publicKey);
     assert.ok(lockerAccountAfter.currentUnlockDate.eq(newUnlockDate));
+
+    const relocked = await event;
+    await program.removeEventListener(listener);
+    assert.ok(relocked.locker.equals(lockerAccountBefore.publicKey));
+    assert.ok(relocked.oldUnlockDate.eq(unlockDate));
+    assert.ok(relocked.newUnlockDate.eq(newUnlockDate));
   });
 
   it('Transfers the ownership', async () => {
@@ -174,6 +234,115 @@ describe('locker', () => {
     assert.ok(lockerAccountFinal.owner.equals(lockerAccountBefore.account.owner));
   });
 
+  it('Blocks locker changes while paused', async () => {
+    const [config, _bump] = await client.findConfigAddress();
+    const configAccount = await program.account.config.fetch(config);
+    const updateConfig = async (paused, allowWithdrawalsWhenPaused) => await program.rpc.updateConfig(
+      {
+        feeInLamports: null,
+        feeInTokenNumerator: null,
+        feeInTokenDenominator: null,
+        feeInStable: null,
+        stableFeeMint: null,
+        feeInUsdCents: null,
+        solPriceFeed: null,
+        feeTiers: null,
+        referralBps: null,
+        feeRecipients: null,
+        mintInfoPermissioned: null,
+        hasLinearEmission: null,
+        paused,
+        allowWithdrawalsWhenPaused,
+      },
+      {
+        accounts: {
+          admin: provider.wallet.publicKey,
+          config,
+          feeWallet: configAccount.feeWallet,
+          countryList: configAccount.countryList,
+        }
+      }
+    );
+
+    const pausedMint = await createMint(provider);
+    const pausedFundingWallet = await serumCmn.createTokenAccount(
+      provider,
+      pausedMint.publicKey,
+      provider.wallet.publicKey,
+    );
+    await pausedMint.mintTo(pausedFundingWallet, provider.wallet.publicKey, [], 2000);
+
+    // half of the tokens are unlocked already, the rest in a few seconds
+    const now = new anchor.BN(Date.now()).divn(1000);
+    const createLocker = async () => await client.createLocker({
+      unlockDate: now.addn(5),
+      countryCode: "RU",
+      startEmission: null,
+      schedule: [
+        { date: now.subn(10), bps: 5000 },
+        { date: now.addn(5), bps: 10000 },
+      ],
+      amount: new anchor.BN(1000),
+      creator,
+      owner: creator,
+      fundingWalletAuthority: creator,
+      fundingWallet: pausedFundingWallet,
+      countryBanlist: countryList.publicKey,
+      feeInSol: true,
+    });
+    const lockerAddress = await createLocker();
+    const locker = {
+      publicKey: lockerAddress,
+      account: await program.account.locker.fetch(lockerAddress),
+    };
+    const withdraw = async () => await client.withdrawFunds({
+      amount: new anchor.BN(1000),
+      locker,
+      targetWallet: pausedFundingWallet,
+      createAssociated: false,
+    });
+    const rejectsPaused = async (action) => await assert.rejects(
+      action,
+      (err: any) => {
+        assert.equal(err.code, 315); // Paused
+        return true;
+      }
+    );
+
+    await updateConfig(true, false);
+
+    await rejectsPaused(async () => await client.transferOwnership({
+      locker,
+      newOwner: newOwner.publicKey,
+    }));
+    await rejectsPaused(createLocker);
+    await rejectsPaused(async () => await client.incrementLock({
+      amount: new anchor.BN(100),
+      locker,
+      fundingWallet: pausedFundingWallet,
+      fundingWalletAuthority: creator,
+    }));
+    await rejectsPaused(async () => await client.splitLocker({
+      amount: new anchor.BN(100),
+      locker,
+      newOwner: creator,
+    }));
+    await rejectsPaused(withdraw);
+
+    await updateConfig(null, true);
+    await withdraw();
+
+    const lockerAccount = await program.account.locker.fetch(lockerAddress);
+    assert.ok(lockerAccount.withdrawnAmount.eqn(500));
+
+    await updateConfig(false, null);
+
+    // the locker is closed, so the next tests see only the first one
+    await lockerClient.utils.sleep(6000);
+    await withdraw();
+    assert.equal(await provider.connection.getAccountInfo(lockerAddress), null);
+  });
+
   it('Increments the lock', async () => {
     const lockers = await program.account.locker.all();
     const lockerAccountBefore = lockers[0];
@@ -256,6 +425,345 @@ describe('locker', () => {
     );
   });
 
+  it('Charges the token fee of increment once', async () => {
+    const feeMint = await createMint(provider);
+    const feeFundingWallet = await serumCmn.createTokenAccount(
+      provider,
+      feeMint.publicKey,
+      provider.wallet.publicKey,
+    );
+    await feeMint.mintTo(feeFundingWallet, provider.wallet.publicKey, [], 20000);
+
+    const lockerAddress = await client.createLocker({
+      unlockDate: unlockDate.addn(100),
+      countryCode: "RU",
+      startEmission: null,
+      amount: new anchor.BN(10000),
+      creator,
+      owner: creator,
+      fundingWalletAuthority: creator,
+      fundingWallet: feeFundingWallet,
+      countryBanlist: countryList.publicKey,
+      feeInSol: false,
+    });
+    const locker = {
+      publicKey: lockerAddress,
+      account: await program.account.locker.fetch(lockerAddress),
+    };
+
+    let listener = null;
+    const event: any = new Promise((resolve, _reject) => {
+      listener = program.addEventListener("FeePaid", (event, _slot) => {
+        resolve(event);
+      });
+    });
+
+    const feeTokenWallet = await client.incrementLock({
+      amount: new anchor.BN(10000),
+      locker,
+      fundingWallet: feeFundingWallet,
+      fundingWalletAuthority: creator,
+    });
+
+    const feePaid = await event;
+    await program.removeEventListener(listener);
+    // 35 bps of the increment, not of the fee
+    assert.ok(feePaid.mint.equals(feeMint.publicKey));
+    assert.ok(feePaid.feeWallet.equals(feeTokenWallet));
+    assert.ok(feePaid.amount.eqn(35));
+
+    const lockerAccount = await program.account.locker.fetch(lockerAddress);
+    assert.ok(lockerAccount.depositedAmount.eqn(9965 + 9965));
+    const feeTokenWalletAccount = await serumCmn.getTokenAccount(provider, feeTokenWallet);
+    assert.ok(feeTokenWalletAccount.amount.eqn(35 + 35));
+  });
+
+  it('Derives locker addresses from creator, mint and nonce', async () => {
+    const pdaMint = await createMint(provider);
+    const pdaFundingWallet = await serumCmn.createTokenAccount(
+      provider,
+      pdaMint.publicKey,
+      provider.wallet.publicKey,
+    );
+    await pdaMint.mintTo(pdaFundingWallet, provider.wallet.publicKey, [], 2000);
+
+    const createPdaLocker = async (nonce) => await client.createLocker({
+      unlockDate: unlockDate.addn(100),
+      countryCode: "RU",
+      startEmission: null,
+      amount: new anchor.BN(1000),
+      creator,
+      owner: creator,
+      fundingWalletAuthority: creator,
+      fundingWallet: pdaFundingWallet,
+      countryBanlist: countryList.publicKey,
+      feeInSol: true,
+      nonce,
+    });
+    const expectedAddress = async (nonce) => {
+      const [address, _bump] = await anchor.web3.PublicKey.findProgramAddress(
+        [
+          new TextEncoder().encode("locker"),
+          creator.toBytes(),
+          pdaMint.publicKey.toBytes(),
+          nonce.toArray('be', 8),
+        ],
+        program.programId,
+      );
+      return address;
+    };
+
+    // the first free nonce is taken unless given explicitly
+    const first = await createPdaLocker(undefined);
+    assert.ok(first.equals(await expectedAddress(new anchor.BN(0))));
+    const second = await createPdaLocker(new anchor.BN(5));
+    assert.ok(second.equals(await expectedAddress(new anchor.BN(5))));
+
+    for (const locker of [first, second]) {
+      const lockerAccount = await program.account.locker.fetch(locker);
+      const [vaultAuthority, vaultBump] = await anchor.web3.PublicKey.findProgramAddress(
+        [locker.toBytes()],
+        program.programId,
+      );
+      assert.equal(lockerAccount.vaultBump, vaultBump);
+      assert.ok(lockerAccount.vault.equals(
+        await lockerClient.utils.associatedAddress(pdaMint.publicKey, vaultAuthority)
+      ));
+    }
+  });
+
+  it('Stores mint and creator of locker at fixed offsets', async () => {
+    const indexedMint = await createMint(provider);
+    const indexedFundingWallet = await serumCmn.createTokenAccount(
+      provider,
+      indexedMint.publicKey,
+      provider.wallet.publicKey,
+    );
+    await indexedMint.mintTo(indexedFundingWallet, provider.wallet.publicKey, [], 2000);
+
+    const now = new anchor.BN(Date.now()).divn(1000);
+    const createIndexedLocker = async (startEmission) => await client.createLocker({
+      unlockDate: now.addn(100),
+      countryCode: "RU",
+      startEmission,
+      amount: new anchor.BN(1000),
+      creator,
+      owner: newOwner.publicKey,
+      fundingWalletAuthority: creator,
+      fundingWallet: indexedFundingWallet,
+      countryBanlist: countryList.publicKey,
+      feeInSol: true,
+    });
+    // start of emission goes before the fields which used to be shifted by it
+    const timeLock = await createIndexedLocker(null);
+    const emissionLock = await createIndexedLocker(now);
+
+    for (const locker of [timeLock, emissionLock]) {
+      const lockerAccount = await program.account.locker.fetch(locker);
+      assert.ok(lockerAccount.mint.equals(indexedMint.publicKey));
+      assert.ok(lockerAccount.creator.equals(creator));
+
+      const data = (await provider.connection.getAccountInfo(locker)).data;
+      const offsets = lockerClient.LOCKER_OFFSETS;
+      assert.equal(data[offsets.VERSION], 1);
+      assert.ok(data.slice(offsets.OWNER, offsets.OWNER + 32).equals(newOwner.publicKey.toBuffer()));
+      assert.ok(data.slice(offsets.CREATOR, offsets.CREATOR + 32).equals(creator.toBuffer()));
+      assert.ok(data.slice(offsets.MINT, offsets.MINT + 32).equals(indexedMint.publicKey.toBuffer()));
+      assert.ok(data.slice(offsets.VAULT, offsets.VAULT + 32).equals(lockerAccount.vault.toBuffer()));
+    }
+
+    // new owner has lockers of other mints too
+    const lockers = await client.getLockersOwnedBy(newOwner.publicKey, indexedMint.publicKey);
+    assert.deepStrictEqual(
+      lockers.map((l) => l.publicKey.toBase58()).sort(),
+      [timeLock.toBase58(), emissionLock.toBase58()].sort(),
+    );
+    assert.equal((await client.getLockersByMint(indexedMint.publicKey)).length, 2);
+    const created = await client.getLockersCreatedBy(creator);
+    assert.ok(created.some((l) => l.publicKey.equals(emissionLock)));
+  });
+
+  it('Keeps mint stats through split and close', async () => {
+    const statsMint = await createMint(provider);
+    const statsFundingWallet = await serumCmn.createTokenAccount(
+      provider,
+      statsMint.publicKey,
+      provider.wallet.publicKey,
+    );
+    await statsMint.mintTo(statsFundingWallet, provider.wallet.publicKey, [], 1000);
+
+    const now = new anchor.BN(Date.now()).divn(1000);
+    const statsUnlockDate = now.addn(3);
+    const lockerAddress = await client.createLocker({
+      unlockDate: statsUnlockDate,
+      countryCode: "RU",
+      startEmission: null,
+      amount: new anchor.BN(1000),
+      creator,
+      owner: creator,
+      fundingWalletAuthority: creator,
+      fundingWallet: statsFundingWallet,
+      countryBanlist: countryList.publicKey,
+      feeInSol: true,
+    });
+
+    let stats = await client.getMintStats(statsMint.publicKey);
+    assert.ok(stats.totalLocked.eqn(1000));
+    assert.ok(stats.lockerCount.eqn(1));
+    assert.ok(stats.unlockDateLowerBound.eq(statsUnlockDate));
+    assert.ok(stats.averageUnlockDate.eq(statsUnlockDate));
+
+    const locker = {
+      publicKey: lockerAddress,
+      account: await program.account.locker.fetch(lockerAddress),
+    };
+    await client.splitLocker({
+      amount: new anchor.BN(400),
+      locker,
+      newOwner: newOwner.publicKey,
+    });
+
+    // split moves tokens between lockers of the same mint
+    stats = await client.getMintStats(statsMint.publicKey);
+    assert.ok(stats.totalLocked.eqn(1000));
+    assert.ok(stats.lockerCount.eqn(2));
+    assert.ok(stats.averageUnlockDate.eq(statsUnlockDate));
+
+    locker.account = await program.account.locker.fetch(lockerAddress);
+    while (true) {
+      try {
+        await client.withdrawFunds({
+          amount: new anchor.BN(600),
+          locker,
+          targetWallet: statsFundingWallet,
+        });
+        break;
+      } catch (err) {
+        assert.equal(err.code, 308); // TooEarlyToWithdraw
+        await lockerClient.utils.sleep(1000);
+      }
+    }
+
+    // withdrawn locker is closed and no longer counted
+    assert.equal(await provider.connection.getAccountInfo(lockerAddress), null);
+    stats = await client.getMintStats(statsMint.publicKey);
+    assert.ok(stats.totalLocked.eqn(400));
+    assert.ok(stats.lockerCount.eqn(1));
+    assert.ok(stats.unlockDateLowerBound.eq(statsUnlockDate));
+  });
+
+  it('Creates locker without fees for exempted wallet', async () => {
+    const [config, _configBump] = await client.findConfigAddress();
+    const [feeExemption, bump] = await client.findFeeExemptionAddress(creator);
+
+    await program.rpc.addFeeExemption(
+      {
+        discountBps: 10000,
+        bump,
+      },
+      {
+        accounts: {
+          admin: provider.wallet.publicKey,
+          config,
+          wallet: creator,
+          feeExemption,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        }
+      }
+    );
+
+    const exemptMint = await createMint(provider);
+    const exemptFundingWallet = await serumCmn.createTokenAccount(
+      provider,
+      exemptMint.publicKey,
+      provider.wallet.publicKey,
+    );
+    await exemptMint.mintTo(exemptFundingWallet, provider.wallet.publicKey, [], 1000);
+
+    const locker = await client.createLocker({
+      unlockDate: unlockDate.addn(100),
+      countryCode: "RU",
+      startEmission: null,
+      amount: new anchor.BN(1000),
+      creator,
+      owner: creator,
+      fundingWalletAuthority: creator,
+      fundingWallet: exemptFundingWallet,
+      countryBanlist: countryList.publicKey,
+      feeInSol: false,
+    });
+
+    let lockerAccount = await program.account.locker.fetch(locker);
+    assert.ok(lockerAccount.depositedAmount.eqn(1000));
+
+    // exemption of the creator applies to increments by the owner
+    const ownerFundingWallet = await serumCmn.createTokenAccount(
+      provider,
+      exemptMint.publicKey,
+      newOwner.publicKey,
+    );
+    await exemptMint.mintTo(ownerFundingWallet, provider.wallet.publicKey, [], 2000);
+    await client.transferOwnership({
+      locker: { publicKey: locker, account: lockerAccount },
+      newOwner: newOwner.publicKey,
+    });
+    const incrementByOwner = async () => await client.incrementLock({
+      amount: new anchor.BN(1000),
+      locker: {
+        publicKey: locker,
+        account: await program.account.locker.fetch(locker),
+      },
+      fundingWallet: ownerFundingWallet,
+      fundingWalletAuthority: newOwner.publicKey,
+      signers: [newOwner],
+    });
+
+    await incrementByOwner();
+    lockerAccount = await program.account.locker.fetch(locker);
+    assert.ok(lockerAccount.depositedAmount.eqn(2000));
+
+    await program.rpc.removeFeeExemption({
+      accounts: {
+        admin: provider.wallet.publicKey,
+        config,
+        feeExemption,
+      }
+    });
+
+    // exemption of the owner doesn't apply to lockers created by others
+    const [ownerFeeExemption, ownerBump] = await client.findFeeExemptionAddress(
+      newOwner.publicKey
+    );
+    await program.rpc.addFeeExemption(
+      {
+        discountBps: 10000,
+        bump: ownerBump,
+      },
+      {
+        accounts: {
+          admin: provider.wallet.publicKey,
+          config,
+          wallet: newOwner.publicKey,
+          feeExemption: ownerFeeExemption,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        }
+      }
+    );
+
+    await incrementByOwner();
+    lockerAccount = await program.account.locker.fetch(locker);
+    // 35 bps of 1000 rounded down
+    assert.ok(lockerAccount.depositedAmount.eqn(2000 + 997));
+
+    await program.rpc.removeFeeExemption({
+      accounts: {
+        admin: provider.wallet.publicKey,
+        config,
+        feeExemption: ownerFeeExemption,
+      }
+    });
+  });
+
   it('Creates locker with linear emission', async () => {
     await mint.mintTo(fundingWallet, provider.wallet.publicKey, [], 1000);
 
@@ -297,5 +805,1579 @@ describe('locker', () => {
     const vaultWallet = await serumCmn.getTokenAccount(provider, lockerAccount.vault);
     // should be 750 but it's hard to guarantree the exact value
     assert.ok(vaultWallet.amount.gten(745) && fundingWalletAccount.amount.lten(755));
-  })
+    assert.ok(lockerAccount.withdrawnAmount.eq(fundingWalletAccount.amount));
+  });
+
+  it('Does not withdraw already claimed emission twice', async () => {
+    const lockers = await client.getLockersOwnedBy(provider.wallet.publicKey);
+    const locker = lockers.find((l) => l.account.startEmission !== null);
+    const withdrawnBefore = locker.account.withdrawnAmount;
+
+    await client.withdrawFunds({
+      amount: new anchor.BN(900),
+      locker,
+      targetWallet: fundingWallet,
+      createAssociated: false,
+    });
+
+    const lockerAccount = await program.account.locker.fetch(locker.publicKey);
+    const fundingWalletAccount = await serumCmn.getTokenAccount(provider, fundingWallet);
+    assert.ok(lockerAccount.withdrawnAmount.eq(fundingWalletAccount.amount));
+    // only a few seconds of emission (50 per second) passed since the first withdrawal
+    assert.ok(lockerAccount.withdrawnAmount.sub(withdrawnBefore).lten(250));
+
+    const vaultWallet = await serumCmn.getTokenAccount(provider, lockerAccount.vault);
+    assert.ok(vaultWallet.amount.add(lockerAccount.withdrawnAmount).eqn(1000));
+  });
+
+  it('Creates locker with cliff', async () => {
+    await mint.mintTo(fundingWallet, provider.wallet.publicKey, [], 1000);
+
+    const now = new anchor.BN(Date.now()).divn(1000);
+    const unlockDate = now.addn(100);
+
+    const locker = await client.createLocker({
+      unlockDate,
+      countryCode: "RU",
+      startEmission: now,
+      cliff: {
+        date: now.addn(4),
+        bps: 2000,
+      },
+      amount: new anchor.BN(1000),
+      creator,
+      owner: creator,
+      fundingWalletAuthority: creator,
+      fundingWallet,
+      countryBanlist: countryList.publicKey,
+      feeInSol: true,
+    });
+
+    let lockerAccount = await program.account.locker.fetch(locker);
+    assert.ok(lockerAccount.cliff.date.eq(now.addn(4)));
+    assert.equal(lockerAccount.cliff.bps, 2000);
+
+    const withdraw = async () => await client.withdrawFunds({
+      amount: new anchor.BN(1000),
+      locker: {
+        publicKey: locker,
+        account: lockerAccount,
+      },
+      targetWallet: fundingWallet,
+      createAssociated: false,
+    });
+
+    await assert.rejects(
+      withdraw,
+      (err) => {
+        assert.equal(err.code, 309); // InvalidAmount
+        return true;
+      }
+    );
+
+    await lockerClient.utils.sleep(5000);
+    await withdraw();
+
+    lockerAccount = await program.account.locker.fetch(locker);
+    // 200 at cliff and a few seconds of emission after that
+    assert.ok(lockerAccount.withdrawnAmount.gten(200) && lockerAccount.withdrawnAmount.lten(250));
+  });
+
+  it('Creates locker with tranche schedule', async () => {
+    await mint.mintTo(fundingWallet, provider.wallet.publicKey, [], 1000);
+
+    const now = new anchor.BN(Date.now()).divn(1000);
+    const unlockDate = now.addn(100);
+
+    const locker = await client.createLocker({
+      unlockDate,
+      countryCode: "RU",
+      startEmission: null,
+      schedule: [
+        { date: now.subn(10), bps: 1000 },
+        { date: now.addn(50), bps: 5000 },
+        { date: unlockDate, bps: 10000 },
+      ],
+      amount: new anchor.BN(1000),
+      creator,
+      owner: creator,
+      fundingWalletAuthority: creator,
+      fundingWallet,
+      countryBanlist: countryList.publicKey,
+      feeInSol: true,
+    });
+
+    let lockerAccount = await program.account.locker.fetch(locker);
+    assert.equal(lockerAccount.schedule.length, 3);
+
+    await client.withdrawFunds({
+      amount: new anchor.BN(1000),
+      locker: {
+        publicKey: locker,
+        account: lockerAccount,
+      },
+      targetWallet: fundingWallet,
+      createAssociated: false,
+    });
+
+    lockerAccount = await program.account.locker.fetch(locker);
+    assert.ok(lockerAccount.withdrawnAmount.eqn(100));
+  })
+
+  it('Withdraws from both parts of unevenly split locker', async () => {
+    await mint.mintTo(fundingWallet, provider.wallet.publicKey, [], 1000);
+
+    const now = new anchor.BN(Date.now()).divn(1000);
+    const unlockDate = now.addn(6);
+
+    const lockerAddress = await client.createLocker({
+      unlockDate,
+      countryCode: "RU",
+      startEmission: null,
+      schedule: [
+        { date: now.subn(10), bps: 3333 },
+        { date: unlockDate, bps: 10000 },
+      ],
+      amount: new anchor.BN(1000),
+      creator,
+      owner: creator,
+      fundingWalletAuthority: creator,
+      fundingWallet,
+      countryBanlist: countryList.publicKey,
+      feeInSol: true,
+    });
+    const locker = {
+      publicKey: lockerAddress,
+      account: await program.account.locker.fetch(lockerAddress),
+    };
+    const withdraw = async (part) => await client.withdrawFunds({
+      amount: new anchor.BN(1000),
+      locker: part,
+      targetWallet: fundingWallet,
+      createAssociated: false,
+    });
+
+    await withdraw(locker);
+
+    // 333 are withdrawn, splitting 100 of the other 667 doesn't divide evenly
+    const newVault = await client.splitLocker({
+      amount: new anchor.BN(100),
+      locker,
+      newOwner: creator,
+    });
+    locker.account = await program.account.locker.fetch(lockerAddress);
+    assert.ok(locker.account.depositedAmount.eqn(850));
+    assert.ok(locker.account.withdrawnAmount.eqn(283));
+    const newLocker = (await program.account.locker.all())
+      .find((l) => l.account.vault.equals(newVault));
+    assert.ok(newLocker.account.depositedAmount.eqn(150));
+    assert.ok(newLocker.account.withdrawnAmount.eqn(50));
+
+    // vested amount of the new part is a token behind, nothing to withdraw yet
+    await assert.rejects(
+      async () => await withdraw(newLocker),
+      (err: any) => {
+        assert.equal(err.code, 309); // InvalidAmount
+        return true;
+      }
+    );
+
+    await lockerClient.utils.sleep(7000);
+    await withdraw(locker);
+    await withdraw(newLocker);
+    assert.equal(await provider.connection.getAccountInfo(lockerAddress), null);
+    assert.equal(await provider.connection.getAccountInfo(newLocker.publicKey), null);
+  });
+
+  it('Rejects mints with transfer hook', async () => {
+    const tokenProgram = lockerClient.utils.TOKEN_2022_PROGRAM_ID;
+    const hookMint = anchor.web3.Keypair.generate();
+    // base mint padded to token account size, account type
+    // and transfer hook extension with authority and no program yet
+    const space = 165 + 1 + 4 + 64;
+    const lamports = await provider.connection.getMinimumBalanceForRentExemption(space);
+    const mintKeys = [{ pubkey: hookMint.publicKey, isSigner: false, isWritable: true }];
+
+    await provider.send(
+      new anchor.web3.Transaction().add(
+        anchor.web3.SystemProgram.createAccount({
+          fromPubkey: creator,
+          newAccountPubkey: hookMint.publicKey,
+          space,
+          lamports,
+          programId: tokenProgram,
+        }),
+        // InitializeTransferHook
+        new anchor.web3.TransactionInstruction({
+          keys: mintKeys,
+          programId: tokenProgram,
+          data: Buffer.concat([Buffer.from([36, 0]), creator.toBuffer(), Buffer.alloc(32)]),
+        }),
+        // InitializeMint2 without freeze authority
+        new anchor.web3.TransactionInstruction({
+          keys: mintKeys,
+          programId: tokenProgram,
+          data: Buffer.concat([Buffer.from([20, 6]), creator.toBuffer(), Buffer.from([0])]),
+        }),
+      ),
+      [hookMint],
+    );
+
+    const hookFundingWallet = await lockerClient.utils.associatedAddress(
+      hookMint.publicKey, creator, tokenProgram
+    );
+    await provider.send(
+      new anchor.web3.Transaction().add(
+        spl.Token.createAssociatedTokenAccountInstruction(
+          spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+          tokenProgram,
+          hookMint.publicKey,
+          hookFundingWallet,
+          creator,
+          creator,
+        ),
+      ),
+    );
+
+    await assert.rejects(
+      async () => await client.createLocker({
+        unlockDate,
+        countryCode: "RU",
+        startEmission: null,
+        amount: new anchor.BN(1000),
+        creator,
+        owner: creator,
+        fundingWalletAuthority: creator,
+        fundingWallet: hookFundingWallet,
+        countryBanlist: countryList.publicKey,
+        feeInSol: true,
+      }),
+      (err: any) => {
+        assert.equal(err.code, 320); // UnsupportedMintExtension
+        return true;
+      }
+    );
+  });
+
+  it('Locks and withdraws Token-2022 tokens', async () => {
+    const tokenProgram = lockerClient.utils.TOKEN_2022_PROGRAM_ID;
+    const mint2022 = await spl.Token.createMint(
+      provider.connection,
+      provider.wallet.payer,
+      creator,
+      null,
+      6,
+      tokenProgram,
+    );
+    // plain account of the base layout size to fund and withdraw to
+    const wallet2022 = await mint2022.createAccount(creator);
+    await mint2022.mintTo(wallet2022, creator, [], 1000);
+
+    const now = new anchor.BN(Date.now()).divn(1000);
+    const lockerAddress = await client.createLocker({
+      unlockDate: now.addn(3),
+      countryCode: "RU",
+      startEmission: null,
+      amount: new anchor.BN(1000),
+      creator,
+      owner: creator,
+      fundingWalletAuthority: creator,
+      fundingWallet: wallet2022,
+      countryBanlist: countryList.publicKey,
+      feeInSol: true,
+    });
+    const locker = {
+      publicKey: lockerAddress,
+      account: await program.account.locker.fetch(lockerAddress),
+    };
+    const vaultInfo = await provider.connection.getAccountInfo(locker.account.vault);
+    assert.ok(vaultInfo.owner.equals(tokenProgram));
+
+    while (true) {
+      try {
+        await client.withdrawFunds({
+          amount: new anchor.BN(1000),
+          locker,
+          targetWallet: wallet2022,
+        });
+        break;
+      } catch (err) {
+        assert.equal(err.code, 308); // TooEarlyToWithdraw
+        await lockerClient.utils.sleep(1000);
+      }
+    }
+
+    const balance = await provider.connection.getTokenAccountBalance(wallet2022);
+    assert.equal(balance.value.amount, "1000");
+    assert.equal(await provider.connection.getAccountInfo(locker.account.vault), null);
+  });
+
+  it('Creates locker with receipt', async () => {
+    await mint.mintTo(fundingWallet, provider.wallet.publicKey, [], 1000);
+
+    const now = new anchor.BN(Date.now()).divn(1000);
+    const unlockDate = now.addn(100);
+
+    const locker = await client.createLocker({
+      unlockDate,
+      countryCode: "RU",
+      startEmission: null,
+      schedule: [
+        { date: now.subn(10), bps: 1000 },
+        { date: unlockDate, bps: 10000 },
+      ],
+      amount: new anchor.BN(1000),
+      creator,
+      owner: creator,
+      fundingWalletAuthority: creator,
+      fundingWallet,
+      countryBanlist: countryList.publicKey,
+      feeInSol: true,
+      receipt: true,
+    });
+
+    let lockerAccount = await program.account.locker.fetch(locker);
+    const receipt = new spl.Token(
+      provider.connection,
+      lockerAccount.receiptMint,
+      lockerClient.utils.TOKEN_PROGRAM_ID,
+      provider.wallet.payer,
+    );
+    const receiptMintInfo = await receipt.getMintInfo();
+    assert.ok(receiptMintInfo.supply.eqn(1));
+    assert.equal(receiptMintInfo.mintAuthority, null);
+
+    await assert.rejects(
+      async () => await client.transferOwnership({
+        locker: { publicKey: locker, account: lockerAccount },
+        newOwner: newOwner.publicKey,
+      }),
+      (err: any) => {
+        assert.equal(err.code, 323); // LockerHasReceipt
+        return true;
+      }
+    );
+
+    await client.withdrawFunds({
+      amount: new anchor.BN(50),
+      locker: { publicKey: locker, account: lockerAccount },
+      targetWallet: fundingWallet,
+      createAssociated: false,
+    });
+
+    const creatorReceipt = await lockerClient.utils.associatedAddress(lockerAccount.receiptMint, creator);
+    const newOwnerReceipt = await receipt.createAssociatedTokenAccount(newOwner.publicKey);
+    await receipt.transfer(creatorReceipt, newOwnerReceipt, provider.wallet.payer, [], 1);
+
+    await assert.rejects(
+      async () => await client.withdrawFunds({
+        amount: new anchor.BN(50),
+        locker: { publicKey: locker, account: lockerAccount },
+        targetWallet: fundingWallet,
+        createAssociated: false,
+      }),
+      (err: any) => {
+        assert.equal(err.code, 322); // ReceiptNotHeld
+        return true;
+      }
+    );
+
+    lockerAccount = await program.account.locker.fetch(locker);
+    assert.ok(lockerAccount.withdrawnAmount.eqn(50));
+  });
+
+  it('Merges lockers', async () => {
+    await mint.mintTo(fundingWallet, provider.wallet.publicKey, [], 200);
+
+    const now = new anchor.BN(Date.now()).divn(1000);
+    const createLocker = async (unlockDate: anchor.BN) => await client.createLocker({
+      unlockDate,
+      countryCode: "RU",
+      startEmission: null,
+      amount: new anchor.BN(100),
+      creator,
+      owner: creator,
+      fundingWalletAuthority: creator,
+      fundingWallet,
+      countryBanlist: countryList.publicKey,
+      feeInSol: true,
+    });
+
+    const source = await createLocker(now.addn(200));
+    const destination = await createLocker(now.addn(100));
+
+    await client.mergeLockers({
+      source: { publicKey: source, account: await program.account.locker.fetch(source) },
+      destination: { publicKey: destination, account: await program.account.locker.fetch(destination) },
+    });
+
+    const destinationAccount = await program.account.locker.fetch(destination);
+    assert.ok(destinationAccount.depositedAmount.eqn(200));
+    assert.ok(destinationAccount.currentUnlockDate.eq(now.addn(200)));
+
+    const vaultAccount = await serumCmn.getTokenAccount(provider, destinationAccount.vault);
+    assert.ok(vaultAccount.amount.eqn(200));
+
+    assert.equal(await provider.connection.getAccountInfo(source), null);
+  });
+
+  it('Creates locker with beneficiaries', async () => {
+    await mint.mintTo(fundingWallet, provider.wallet.publicKey, [], 1000);
+
+    const now = new anchor.BN(Date.now()).divn(1000);
+    const unlockDate = now.addn(100);
+
+    const locker = await client.createLocker({
+      unlockDate,
+      countryCode: "RU",
+      startEmission: null,
+      schedule: [
+        { date: now.subn(10), bps: 1000 },
+        { date: unlockDate, bps: 10000 },
+      ],
+      amount: new anchor.BN(1000),
+      creator,
+      owner: creator,
+      fundingWalletAuthority: creator,
+      fundingWallet,
+      countryBanlist: countryList.publicKey,
+      feeInSol: true,
+      beneficiaries: [
+        { owner: creator, bps: 7000 },
+        { owner: newOwner.publicKey, bps: 3000 },
+      ],
+    });
+
+    let lockerAccount = await program.account.locker.fetch(locker);
+    const newOwnerWallet = await serumCmn.createTokenAccount(
+      provider,
+      mint.publicKey,
+      newOwner.publicKey,
+    );
+
+    await client.withdrawFunds({
+      amount: new anchor.BN(1000),
+      locker: { publicKey: locker, account: lockerAccount },
+      targetWallet: fundingWallet,
+      createAssociated: false,
+    });
+    await client.withdrawFunds({
+      amount: new anchor.BN(1000),
+      locker: { publicKey: locker, account: lockerAccount },
+      targetWallet: newOwnerWallet,
+      createAssociated: false,
+      beneficiary: newOwner.publicKey,
+      signers: [newOwner],
+    });
+
+    lockerAccount = await program.account.locker.fetch(locker);
+    assert.ok(lockerAccount.withdrawnAmount.eqn(100));
+    assert.ok(lockerAccount.beneficiaries[0].withdrawnAmount.eqn(70));
+    assert.ok(lockerAccount.beneficiaries[1].withdrawnAmount.eqn(30));
+
+    const heir = anchor.web3.Keypair.generate();
+    await client.transferOwnership({
+      locker: { publicKey: locker, account: lockerAccount },
+      owner: newOwner.publicKey,
+      newOwner: heir.publicKey,
+      signers: [newOwner],
+    });
+
+    lockerAccount = await program.account.locker.fetch(locker);
+    assert.ok(lockerAccount.owner.equals(creator));
+    assert.ok(lockerAccount.beneficiaries[1].owner.equals(heir.publicKey));
+    assert.ok(lockerAccount.beneficiaries[1].withdrawnAmount.eqn(30));
+
+    // the owner managing the locker hands over only its own share
+    const otherHeir = anchor.web3.Keypair.generate();
+    await client.transferOwnership({
+      locker: { publicKey: locker, account: lockerAccount },
+      newOwner: otherHeir.publicKey,
+    });
+
+    lockerAccount = await program.account.locker.fetch(locker);
+    assert.ok(lockerAccount.owner.equals(creator));
+    assert.ok(lockerAccount.beneficiaries[0].owner.equals(otherHeir.publicKey));
+
+    // and can't hand over the locker once it has no share
+    await assert.rejects(
+      async () => await client.transferOwnership({
+        locker: { publicKey: locker, account: lockerAccount },
+        newOwner: heir.publicKey,
+      }),
+      (err: any) => {
+        assert.equal(err.code, 326); // NotBeneficiary
+        return true;
+      }
+    );
+  });
+
+  it('Withdraws to locker beneficiary only', async () => {
+    await mint.mintTo(fundingWallet, provider.wallet.publicKey, [], 1000);
+
+    const now = new anchor.BN(Date.now()).divn(1000);
+    const unlockDate = now.addn(100);
+
+    const locker = await client.createLocker({
+      unlockDate,
+      countryCode: "RU",
+      startEmission: null,
+      schedule: [
+        { date: now.subn(10), bps: 1000 },
+        { date: unlockDate, bps: 10000 },
+      ],
+      amount: new anchor.BN(1000),
+      creator,
+      owner: creator,
+      fundingWalletAuthority: creator,
+      fundingWallet,
+      countryBanlist: countryList.publicKey,
+      feeInSol: true,
+    });
+
+    let lockerAccount = await program.account.locker.fetch(locker);
+    await client.setBeneficiary({
+      locker: { publicKey: locker, account: lockerAccount },
+      beneficiary: newOwner.publicKey,
+    });
+    lockerAccount = await program.account.locker.fetch(locker);
+    assert.ok(lockerAccount.beneficiary.equals(newOwner.publicKey));
+
+    await assert.rejects(
+      async () => await client.withdrawFunds({
+        amount: new anchor.BN(100),
+        locker: { publicKey: locker, account: lockerAccount },
+        targetWallet: fundingWallet,
+        createAssociated: false,
+      }),
+      (err: any) => {
+        assert.equal(err.code, 328); // InvalidTargetWallet
+        return true;
+      }
+    );
+
+    const beneficiaryWallet = await serumCmn.createTokenAccount(
+      provider,
+      mint.publicKey,
+      newOwner.publicKey,
+    );
+    await client.withdrawFunds({
+      amount: new anchor.BN(100),
+      locker: { publicKey: locker, account: lockerAccount },
+      targetWallet: beneficiaryWallet,
+      createAssociated: false,
+    });
+
+    const beneficiaryWalletAccount = await serumCmn.getTokenAccount(provider, beneficiaryWallet);
+    assert.ok(beneficiaryWalletAccount.amount.eqn(100));
+  });
+
+  it('Revokes unvested tokens', async () => {
+    await mint.mintTo(fundingWallet, provider.wallet.publicKey, [], 1000);
+
+    const now = new anchor.BN(Date.now()).divn(1000);
+    const unlockDate = now.addn(100);
+
+    // creator could take back a plain time lock at any moment
+    await assert.rejects(
+      async () => await client.createLocker({
+        unlockDate,
+        countryCode: "RU",
+        startEmission: null,
+        amount: new anchor.BN(1000),
+        creator,
+        owner: newOwner.publicKey,
+        fundingWalletAuthority: creator,
+        fundingWallet,
+        countryBanlist: countryList.publicKey,
+        feeInSol: true,
+        revocable: true,
+      }),
+      (err: any) => {
+        assert.equal(err.code, 346); // RevocableWithoutEmission
+        return true;
+      }
+    );
+
+    const statsBefore = await client.getMintStats(mint.publicKey);
+    const locker = await client.createLocker({
+      unlockDate,
+      countryCode: "RU",
+      startEmission: null,
+      schedule: [
+        { date: now.subn(10), bps: 2000 },
+        { date: unlockDate, bps: 10000 },
+      ],
+      amount: new anchor.BN(1000),
+      creator,
+      owner: newOwner.publicKey,
+      fundingWalletAuthority: creator,
+      fundingWallet,
+      countryBanlist: countryList.publicKey,
+      feeInSol: true,
+      revocable: true,
+    });
+
+    let lockerAccount = await program.account.locker.fetch(locker);
+    assert.ok(lockerAccount.creator.equals(creator));
+
+    // revocable tokens are not counted as locked
+    let stats = await client.getMintStats(mint.publicKey);
+    assert.ok(stats.totalLocked.eq(statsBefore.totalLocked));
+    assert.ok(stats.revocableLocked.eq(statsBefore.revocableLocked.addn(1000)));
+
+    await assert.rejects(
+      async () => await client.incrementLock({
+        amount: new anchor.BN(1000),
+        locker: { publicKey: locker, account: lockerAccount },
+        fundingWallet,
+        fundingWalletAuthority: creator,
+      }),
+      (err: any) => {
+        assert.equal(err.code, 347); // CannotIncrementRevocable
+        return true;
+      }
+    );
+
+    const fundingWalletBefore = await serumCmn.getTokenAccount(provider, fundingWallet);
+    await client.revoke({
+      locker: { publicKey: locker, account: lockerAccount },
+      targetWallet: fundingWallet,
+    });
+    const fundingWalletAfter = await serumCmn.getTokenAccount(provider, fundingWallet);
+    assert.ok(fundingWalletAfter.amount.sub(fundingWalletBefore.amount).eqn(800));
+
+    lockerAccount = await program.account.locker.fetch(locker);
+    assert.ok(lockerAccount.depositedAmount.eqn(200));
+    assert.ok(!lockerAccount.revocable);
+    assert.equal(lockerAccount.schedule.length, 0);
+
+    const vaultAccount = await serumCmn.getTokenAccount(provider, lockerAccount.vault);
+    assert.ok(vaultAccount.amount.eqn(200));
+
+    // vested part is locked for good after the revocation
+    stats = await client.getMintStats(mint.publicKey);
+    assert.ok(stats.totalLocked.eq(statsBefore.totalLocked.addn(200)));
+    assert.ok(stats.revocableLocked.eq(statsBefore.revocableLocked));
+  });
+
+  it('Manages locker by delegate', async () => {
+    await mint.mintTo(fundingWallet, provider.wallet.publicKey, [], 2000);
+
+    const now = new anchor.BN(Date.now()).divn(1000);
+    const unlockDate = now.addn(100);
+
+    const locker = await client.createLocker({
+      unlockDate,
+      countryCode: "RU",
+      startEmission: null,
+      schedule: [
+        { date: now.subn(10), bps: 1000 },
+        { date: unlockDate, bps: 10000 },
+      ],
+      amount: new anchor.BN(1000),
+      creator,
+      owner: newOwner.publicKey,
+      fundingWalletAuthority: creator,
+      fundingWallet,
+      countryBanlist: countryList.publicKey,
+      feeInSol: true,
+    });
+
+    let lockerAccount = await program.account.locker.fetch(locker);
+    await client.setDelegate({
+      locker: { publicKey: locker, account: lockerAccount },
+      delegate: creator,
+      permissions: lockerClient.DELEGATE_PERMISSIONS.RELOCK
+        | lockerClient.DELEGATE_PERMISSIONS.WITHDRAW,
+      signers: [newOwner],
+    });
+
+    lockerAccount = await program.account.locker.fetch(locker);
+    assert.ok(lockerAccount.delegate.equals(creator));
+
+    // delegate gets past the permission check, but tranches can't be moved
+    await assert.rejects(
+      async () => await client.relock({
+        locker: { publicKey: locker, account: lockerAccount },
+        unlockDate: unlockDate.addn(100),
+        delegate: creator,
+      }),
+      (err: any) => {
+        assert.equal(err.code, 344); // CannotRelockSchedule
+        return true;
+      }
+    );
+    lockerAccount = await program.account.locker.fetch(locker);
+    assert.ok(lockerAccount.currentUnlockDate.eq(unlockDate));
+
+    await assert.rejects(
+      async () => await client.incrementLock({
+        amount: new anchor.BN(1000),
+        locker: { publicKey: locker, account: lockerAccount },
+        fundingWallet,
+        fundingWalletAuthority: creator,
+      }),
+      (err: any) => {
+        assert.equal(err.code, 143); // ConstraintRaw
+        return true;
+      }
+    );
+
+    await assert.rejects(
+      async () => await client.withdrawFunds({
+        amount: new anchor.BN(100),
+        locker: { publicKey: locker, account: lockerAccount },
+        targetWallet: fundingWallet,
+        createAssociated: false,
+        delegate: creator,
+      }),
+      (err: any) => {
+        assert.equal(err.code, 328); // InvalidTargetWallet
+        return true;
+      }
+    );
+
+    const ownerWallet = await serumCmn.createTokenAccount(
+      provider,
+      mint.publicKey,
+      newOwner.publicKey,
+    );
+    await client.withdrawFunds({
+      amount: new anchor.BN(100),
+      locker: { publicKey: locker, account: lockerAccount },
+      targetWallet: ownerWallet,
+      createAssociated: false,
+      delegate: creator,
+    });
+
+    const ownerWalletAccount = await serumCmn.getTokenAccount(provider, ownerWallet);
+    assert.ok(ownerWalletAccount.amount.eqn(100));
+  });
+
+  it('Shares fees with referrer', async () => {
+    const [config, _bump] = await client.findConfigAddress();
+    let configAccount = await program.account.config.fetch(config);
+    const updateReferralRate = async (referralBps) => await program.rpc.updateConfig(
+      {
+        feeInLamports: null,
+        feeInTokenNumerator: null,
+        feeInTokenDenominator: null,
+        feeInStable: null,
+        stableFeeMint: null,
+        feeInUsdCents: null,
+        solPriceFeed: null,
+        feeTiers: null,
+        referralBps,
+        feeRecipients: null,
+        mintInfoPermissioned: null,
+        hasLinearEmission: null,
+        paused: null,
+        allowWithdrawalsWhenPaused: null,
+      },
+      {
+        accounts: {
+          admin: provider.wallet.publicKey,
+          config,
+          feeWallet: configAccount.feeWallet,
+          countryList: configAccount.countryList,
+        }
+      }
+    );
+
+    // most of the fee stays with the fee wallet
+    await assert.rejects(
+      async () => await updateReferralRate(3001),
+      (err: any) => {
+        assert.equal(err.code, 331); // InvalidReferralRate
+        return true;
+      }
+    );
+    await updateReferralRate(3000);
+
+    const referralMint = await createMint(provider);
+    const referralFundingWallet = await serumCmn.createTokenAccount(
+      provider,
+      referralMint.publicKey,
+      provider.wallet.publicKey,
+    );
+    await referralMint.mintTo(referralFundingWallet, provider.wallet.publicKey, [], 10000);
+
+    // creator can't refer itself to get part of its own fee back
+    await assert.rejects(
+      async () => await client.createLocker({
+        unlockDate: unlockDate.addn(100),
+        countryCode: "RU",
+        startEmission: null,
+        amount: new anchor.BN(10000),
+        creator,
+        owner: creator,
+        fundingWalletAuthority: creator,
+        fundingWallet: referralFundingWallet,
+        countryBanlist: countryList.publicKey,
+        feeInSol: false,
+        referrer: creator,
+      }),
+      (err: any) => {
+        assert.equal(err.code, 330); // InvalidReferrer
+        return true;
+      }
+    );
+
+    const referrer = anchor.web3.Keypair.generate();
+    const createReferredLocker = async () => await client.createLocker({
+      unlockDate: unlockDate.addn(100),
+      countryCode: "RU",
+      startEmission: null,
+      amount: new anchor.BN(10000),
+      creator,
+      owner: creator,
+      fundingWalletAuthority: creator,
+      fundingWallet: referralFundingWallet,
+      countryBanlist: countryList.publicKey,
+      feeInSol: false,
+      referrer: referrer.publicKey,
+    });
+
+    // only referrers added by admin are paid
+    await assert.rejects(
+      createReferredLocker,
+      (err: any) => {
+        assert.equal(err.code, 330); // InvalidReferrer
+        return true;
+      }
+    );
+
+    const [referrerAccount, referrerBump] = await client.findReferrerAddress(referrer.publicKey);
+    await program.rpc.addReferrer(
+      referrerBump,
+      {
+        accounts: {
+          admin: provider.wallet.publicKey,
+          config,
+          wallet: referrer.publicKey,
+          referrer: referrerAccount,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        }
+      }
+    );
+    await createReferredLocker();
+
+    // 30% of 35 tokens fee rounding in favor of fee wallet
+    const referrerWallet = await lockerClient.utils.associatedAddress(
+      referralMint.publicKey, referrer.publicKey
+    );
+    const feeTokenWallet = await lockerClient.utils.associatedAddress(
+      referralMint.publicKey, configAccount.feeWallet
+    );
+    const referrerWalletAccount = await serumCmn.getTokenAccount(provider, referrerWallet);
+    assert.ok(referrerWalletAccount.amount.eqn(10));
+    const feeTokenWalletAccount = await serumCmn.getTokenAccount(provider, feeTokenWallet);
+    assert.ok(feeTokenWalletAccount.amount.eqn(25));
+
+    await program.rpc.removeReferrer({
+      accounts: {
+        admin: provider.wallet.publicKey,
+        config,
+        referrer: referrerAccount,
+      }
+    });
+    await updateReferralRate(0);
+  });
+
+  it('Shares fees with fee recipients', async () => {
+    const [config, _bump] = await client.findConfigAddress();
+    let configAccount = await program.account.config.fetch(config);
+    const updateFeeRecipients = async (feeRecipients) => await program.rpc.updateConfig(
+      {
+        feeInLamports: null,
+        feeInTokenNumerator: null,
+        feeInTokenDenominator: null,
+        feeInStable: null,
+        stableFeeMint: null,
+        feeInUsdCents: null,
+        solPriceFeed: null,
+        feeTiers: null,
+        referralBps: null,
+        feeRecipients,
+        mintInfoPermissioned: null,
+        hasLinearEmission: null,
+        paused: null,
+        allowWithdrawalsWhenPaused: null,
+      },
+      {
+        accounts: {
+          admin: provider.wallet.publicKey,
+          config,
+          feeWallet: configAccount.feeWallet,
+          countryList: configAccount.countryList,
+        }
+      }
+    );
+
+    const buyback = anchor.web3.Keypair.generate();
+    const partner = anchor.web3.Keypair.generate();
+
+    try {
+      await updateFeeRecipients([
+        { wallet: buyback.publicKey, bps: 6000 },
+        { wallet: partner.publicKey, bps: 6000 },
+      ]);
+      assert.ok(false);
+    } catch (err) {
+      assert.equal(err.code, 332);
+    }
+
+    await updateFeeRecipients([
+      { wallet: buyback.publicKey, bps: 3000 },
+      { wallet: partner.publicKey, bps: 1000 },
+    ]);
+
+    const sharedMint = await createMint(provider);
+    const sharedFundingWallet = await serumCmn.createTokenAccount(
+      provider,
+      sharedMint.publicKey,
+      provider.wallet.publicKey,
+    );
+    await sharedMint.mintTo(sharedFundingWallet, provider.wallet.publicKey, [], 10000);
+
+    await client.createLocker({
+      unlockDate: unlockDate.addn(100),
+      countryCode: "RU",
+      startEmission: null,
+      amount: new anchor.BN(10000),
+      creator,
+      owner: creator,
+      fundingWalletAuthority: creator,
+      fundingWallet: sharedFundingWallet,
+      countryBanlist: countryList.publicKey,
+      feeInSol: false,
+    });
+
+    // 35 tokens fee: 10 to buyback, 3 to partner and the rest to fee wallet
+    const tokenBalance = async (wallet) => {
+      const address = await lockerClient.utils.associatedAddress(sharedMint.publicKey, wallet);
+      return (await serumCmn.getTokenAccount(provider, address)).amount;
+    };
+    assert.ok((await tokenBalance(buyback.publicKey)).eqn(10));
+    assert.ok((await tokenBalance(partner.publicKey)).eqn(3));
+    assert.ok((await tokenBalance(configAccount.feeWallet)).eqn(22));
+
+    await updateFeeRecipients([]);
+  });
+
+  it('Pays flat fee in stable token', async () => {
+    const [config, _bump] = await client.findConfigAddress();
+    let configAccount = await program.account.config.fetch(config);
+
+    const stableMint = await createMint(provider);
+    const creatorStableWallet = await stableMint.createAssociatedTokenAccount(creator);
+    await stableMint.mintTo(creatorStableWallet, provider.wallet.publicKey, [], 80_000000);
+
+    const updateStableFee = async (feeInStable, stableFeeMint) => await program.rpc.updateConfig(
+      {
+        feeInLamports: null,
+        feeInTokenNumerator: null,
+        feeInTokenDenominator: null,
+        feeInStable,
+        stableFeeMint,
+        feeInUsdCents: null,
+        solPriceFeed: null,
+        feeTiers: null,
+        referralBps: null,
+        feeRecipients: null,
+        mintInfoPermissioned: null,
+        hasLinearEmission: null,
+        paused: null,
+        allowWithdrawalsWhenPaused: null,
+      },
+      {
+        accounts: {
+          admin: provider.wallet.publicKey,
+          config,
+          feeWallet: configAccount.feeWallet,
+          countryList: configAccount.countryList,
+        }
+      }
+    );
+
+    await updateStableFee(new anchor.BN(50_000000), stableMint.publicKey);
+
+    const paidMint = await createMint(provider);
+    const paidFundingWallet = await serumCmn.createTokenAccount(
+      provider,
+      paidMint.publicKey,
+      provider.wallet.publicKey,
+    );
+    await paidMint.mintTo(paidFundingWallet, provider.wallet.publicKey, [], 10000);
+
+    const createStableLocker = async (feeInSol) => await client.createLocker({
+      unlockDate: unlockDate.addn(100),
+      countryCode: "RU",
+      startEmission: null,
+      amount: new anchor.BN(10000),
+      creator,
+      owner: creator,
+      fundingWalletAuthority: creator,
+      fundingWallet: paidFundingWallet,
+      countryBanlist: countryList.publicKey,
+      feeInSol,
+      feeInStable: true,
+    });
+
+    // flat fee in stable token can't be combined with fee in locked tokens
+    await assert.rejects(
+      async () => await createStableLocker(false),
+      (err: any) => {
+        assert.equal(err.code, 348); // InvalidFeeOption
+        return true;
+      }
+    );
+
+    const feeWalletLamports = await provider.connection.getBalance(configAccount.feeWallet);
+    const locker = await createStableLocker(true);
+
+    const lockerAccount = await program.account.locker.fetch(locker);
+    assert.ok(lockerAccount.depositedAmount.eqn(10000));
+
+    const feeStableWallet = await lockerClient.utils.associatedAddress(
+      stableMint.publicKey, configAccount.feeWallet
+    );
+    const feeStableWalletAccount = await serumCmn.getTokenAccount(provider, feeStableWallet);
+    assert.ok(feeStableWalletAccount.amount.eqn(50_000000));
+    const creatorStableWalletAccount = await serumCmn.getTokenAccount(provider, creatorStableWallet);
+    assert.ok(creatorStableWalletAccount.amount.eqn(30_000000));
+    assert.equal(await provider.connection.getBalance(configAccount.feeWallet), feeWalletLamports);
+
+    const [mintInfo, _mintInfoBump] = await client.findMintInfoAddress(paidMint.publicKey);
+    const mintInfoAccount = await program.account.mintInfo.fetch(mintInfo);
+    assert.ok(mintInfoAccount.feePaid);
+
+    // default pubkey turns the fee in stable token off
+    await updateStableFee(null, new anchor.web3.PublicKey(0));
+    configAccount = await program.account.config.fetch(config);
+    assert.equal(configAccount.stableFeeMint, null);
+  });
+
+  it('Prices SOL fee in USD', async () => {
+    const priceFeedProgram = anchor.workspace.MockPriceFeed as anchor.Program<MockPriceFeed>;
+    const [config, _bump] = await client.findConfigAddress();
+    let configAccount = await program.account.config.fetch(config);
+
+    const priceFeed = anchor.web3.Keypair.generate();
+    const PRICE_ACCOUNT_LEN = 3312;
+    // SOL costs $150 with confidence interval of 5 cents
+    await priceFeedProgram.rpc.writePrice(
+      new anchor.BN(150_00000000),
+      new anchor.BN(5000000),
+      -8,
+      1,
+      {
+        accounts: {
+          priceFeed: priceFeed.publicKey,
+          clock: anchor.web3.SYSVAR_CLOCK_PUBKEY,
+        },
+        instructions: [
+          anchor.web3.SystemProgram.createAccount({
+            fromPubkey: provider.wallet.publicKey,
+            newAccountPubkey: priceFeed.publicKey,
+            space: PRICE_ACCOUNT_LEN,
+            lamports: await provider.connection.getMinimumBalanceForRentExemption(
+              PRICE_ACCOUNT_LEN
+            ),
+            programId: priceFeedProgram.programId,
+          }),
+        ],
+        signers: [priceFeed],
+      }
+    );
+
+    const updateUsdFee = async (solPriceFeed) => await program.rpc.updateConfig(
+      {
+        feeInLamports: null,
+        feeInTokenNumerator: null,
+        feeInTokenDenominator: null,
+        feeInStable: null,
+        stableFeeMint: null,
+        feeInUsdCents: new anchor.BN(1500),
+        solPriceFeed,
+        feeTiers: null,
+        referralBps: null,
+        feeRecipients: null,
+        mintInfoPermissioned: null,
+        hasLinearEmission: null,
+        paused: null,
+        allowWithdrawalsWhenPaused: null,
+      },
+      {
+        accounts: {
+          admin: provider.wallet.publicKey,
+          config,
+          feeWallet: configAccount.feeWallet,
+          countryList: configAccount.countryList,
+        }
+      }
+    );
+    await updateUsdFee({
+      account: priceFeed.publicKey,
+      maxAge: new anchor.BN(60),
+      maxConfidenceBps: 100,
+    });
+
+    const createPricedLocker = async () => {
+      const pricedMint = await createMint(provider);
+      const pricedFundingWallet = await serumCmn.createTokenAccount(
+        provider,
+        pricedMint.publicKey,
+        provider.wallet.publicKey,
+      );
+      await pricedMint.mintTo(pricedFundingWallet, provider.wallet.publicKey, [], 10000);
+
+      return await client.createLocker({
+        unlockDate: unlockDate.addn(100),
+        countryCode: "RU",
+        startEmission: null,
+        amount: new anchor.BN(10000),
+        creator,
+        owner: creator,
+        fundingWalletAuthority: creator,
+        fundingWallet: pricedFundingWallet,
+        countryBanlist: countryList.publicKey,
+        feeInSol: true,
+      });
+    };
+
+    // $15 at $150 per SOL
+    const feeWalletLamports = await provider.connection.getBalance(configAccount.feeWallet);
+    await createPricedLocker();
+    assert.equal(
+      await provider.connection.getBalance(configAccount.feeWallet),
+      feeWalletLamports + 100_000000,
+    );
+
+    // confidence interval of $3 is 2% of the price
+    await priceFeedProgram.rpc.writePrice(
+      new anchor.BN(150_00000000),
+      new anchor.BN(3_00000000),
+      -8,
+      1,
+      {
+        accounts: {
+          priceFeed: priceFeed.publicKey,
+          clock: anchor.web3.SYSVAR_CLOCK_PUBKEY,
+        },
+        signers: [priceFeed],
+      }
+    );
+    try {
+      await createPricedLocker();
+      assert.ok(false);
+    } catch (err) {
+      assert.equal(err.code, 338);
+    }
+
+    await updateUsdFee({
+      account: new anchor.web3.PublicKey(0),
+      maxAge: new anchor.BN(0),
+      maxConfidenceBps: 0,
+    });
+  });
+
+  it('Charges fee of the tier reached by lock duration', async () => {
+    const [config, _bump] = await client.findConfigAddress();
+    let configAccount = await program.account.config.fetch(config);
+    const updateTokenFee = async (feeInTokenNumerator, feeTiers) => await program.rpc.updateConfig(
+      {
+        feeInLamports: null,
+        feeInTokenNumerator,
+        feeInTokenDenominator: null,
+        feeInStable: null,
+        stableFeeMint: null,
+        feeInUsdCents: null,
+        solPriceFeed: null,
+        feeTiers,
+        referralBps: null,
+        feeRecipients: null,
+        mintInfoPermissioned: null,
+        hasLinearEmission: null,
+        paused: null,
+        allowWithdrawalsWhenPaused: null,
+      },
+      {
+        accounts: {
+          admin: provider.wallet.publicKey,
+          config,
+          feeWallet: configAccount.feeWallet,
+          countryList: configAccount.countryList,
+        }
+      }
+    );
+    const feeTier = (minDuration, feeInTokenNumerator) => ({
+      minDuration: new anchor.BN(minDuration),
+      feeInLamports: new anchor.BN(1000000000),
+      feeInTokenNumerator: new anchor.BN(feeInTokenNumerator),
+      feeInTokenDenominator: new anchor.BN(10000),
+      feeInStable: new anchor.BN(0),
+      feeInUsdCents: new anchor.BN(0),
+    });
+
+    // fee can't be more than the locked amount
+    await assert.rejects(
+      async () => await updateTokenFee(null, [feeTier(1000, 10001)]),
+      (err: any) => {
+        assert.equal(err.code, 317); // InvalidFeeTiers
+        return true;
+      }
+    );
+    await assert.rejects(
+      async () => await updateTokenFee(new anchor.BN(10001), null),
+      (err: any) => {
+        assert.equal(err.code, 345); // InvalidTokenFee
+        return true;
+      }
+    );
+
+    await updateTokenFee(null, [feeTier(1000, 20), feeTier(2000, 10)]);
+
+    const createTieredLocker = async (lockDuration) => {
+      const tieredMint = await createMint(provider);
+      const tieredFundingWallet = await serumCmn.createTokenAccount(
+        provider,
+        tieredMint.publicKey,
+        provider.wallet.publicKey,
+      );
+      await tieredMint.mintTo(tieredFundingWallet, provider.wallet.publicKey, [], 10000);
+
+      const lockerAddress = await client.createLocker({
+        unlockDate: new anchor.BN(Date.now() / 1000 + lockDuration),
+        countryCode: "RU",
+        startEmission: null,
+        amount: new anchor.BN(10000),
+        creator,
+        owner: creator,
+        fundingWalletAuthority: creator,
+        fundingWallet: tieredFundingWallet,
+        countryBanlist: countryList.publicKey,
+        feeInSol: false,
+      });
+      return await program.account.locker.fetch(lockerAddress);
+    };
+
+    // base fee below the first tier, then 20 and 10 bps around the boundary
+    assert.ok((await createTieredLocker(900)).depositedAmount.eqn(9965));
+    assert.ok((await createTieredLocker(1900)).depositedAmount.eqn(9980));
+    assert.ok((await createTieredLocker(2100)).depositedAmount.eqn(9990));
+
+    await updateTokenFee(null, []);
+  });
+
+  it('Relocks locker with linear emission keeping vested amount', async () => {
+    const emissionMint = await createMint(provider);
+    const emissionFundingWallet = await serumCmn.createTokenAccount(
+      provider,
+      emissionMint.publicKey,
+      provider.wallet.publicKey,
+    );
+    await emissionMint.mintTo(emissionFundingWallet, provider.wallet.publicKey, [], 1000);
+
+    // half of the period has passed
+    const now = new anchor.BN(Date.now()).divn(1000);
+    const lockerAddress = await client.createLocker({
+      unlockDate: now.addn(100),
+      countryCode: "RU",
+      startEmission: now.subn(100),
+      amount: new anchor.BN(1000),
+      creator,
+      owner: creator,
+      fundingWalletAuthority: creator,
+      fundingWallet: emissionFundingWallet,
+      countryBanlist: countryList.publicKey,
+      feeInSol: true,
+    });
+    let locker = {
+      publicKey: lockerAddress,
+      account: await program.account.locker.fetch(lockerAddress),
+    };
+
+    const newUnlockDate = now.addn(1000);
+    await client.relock({ locker, unlockDate: newUnlockDate });
+
+    locker.account = await program.account.locker.fetch(lockerAddress);
+    assert.ok(locker.account.currentUnlockDate.eq(newUnlockDate));
+    // should be 500 but it's hard to guarantee the exact value
+    assert.ok(locker.account.vestedAtRelock.gten(490) && locker.account.vestedAtRelock.lten(510));
+    assert.ok(locker.account.startEmission.gte(now));
+
+    // relock a tenth of the tokens even later, the rest keeps its date
+    const partUnlockDate = now.addn(2000);
+    await client.relock({ locker, unlockDate: partUnlockDate, amount: new anchor.BN(100) });
+
+    const lockers = await client.getLockersOwnedBy(provider.wallet.publicKey);
+    const part = lockers.find((l) => l.account.currentUnlockDate.eq(partUnlockDate));
+    assert.ok(part.account.depositedAmount.eqn(100));
+    // split off part was relocked after a few more seconds of emission
+    assert.ok(part.account.vestedAtRelock.gte(locker.account.vestedAtRelock.divn(10)));
+
+    const rest = await program.account.locker.fetch(lockerAddress);
+    assert.ok(rest.depositedAmount.eqn(900));
+    assert.ok(rest.currentUnlockDate.eq(newUnlockDate));
+    assert.ok(rest.vestedAtRelock.gte(locker.account.vestedAtRelock.muln(9).divn(10)));
+
+    // 333 of 900 don't divide vested at relock amount evenly,
+    // the rest keeps its part rounded up
+    const thirdVault = await client.splitLocker({
+      amount: new anchor.BN(333),
+      locker: { publicKey: lockerAddress, account: rest },
+      newOwner: creator,
+    });
+    const kept = await program.account.locker.fetch(lockerAddress);
+    const third = (await program.account.locker.all())
+      .find((l) => l.account.vault.equals(thirdVault));
+    assert.ok(kept.depositedAmount.eqn(567));
+    assert.ok(kept.vestedAtRelock.add(third.account.vestedAtRelock).eq(rest.vestedAtRelock));
+    assert.ok(kept.vestedAtRelock.muln(900).gte(rest.vestedAtRelock.muln(567)));
+
+    // both parts have vested tokens to withdraw right away
+    for (const l of [{ publicKey: lockerAddress, account: kept }, third]) {
+      await client.withdrawFunds({
+        amount: new anchor.BN(1000),
+        locker: l,
+        targetWallet: emissionFundingWallet,
+        createAssociated: false,
+      });
+      const withdrawn = await program.account.locker.fetch(l.publicKey);
+      assert.ok(withdrawn.withdrawnAmount.gte(l.account.vestedAtRelock));
+    }
+  });
+
+  it('Unlocks early only after approval and delay', async () => {
+    const earlyMint = await createMint(provider);
+    const earlyFundingWallet = await serumCmn.createTokenAccount(
+      provider,
+      earlyMint.publicKey,
+      provider.wallet.publicKey,
+    );
+    await earlyMint.mintTo(earlyFundingWallet, provider.wallet.publicKey, [], 10000);
+
+    const lockerAddress = await client.createLocker({
+      unlockDate: unlockDate.addn(100),
+      countryCode: "RU",
+      startEmission: null,
+      amount: new anchor.BN(10000),
+      creator,
+      owner: creator,
+      fundingWalletAuthority: creator,
+      fundingWallet: earlyFundingWallet,
+      countryBanlist: countryList.publicKey,
+      feeInSol: true,
+    });
+    const locker = {
+      publicKey: lockerAddress,
+      account: await program.account.locker.fetch(lockerAddress),
+    };
+
+    // request of the previous owner doesn't outlive the transfer
+    await client.requestEmergencyUnlock({ locker });
+    await client.transferOwnership({ locker, newOwner: newOwner.publicKey });
+    const transferredLocker = {
+      publicKey: lockerAddress,
+      account: await program.account.locker.fetch(lockerAddress),
+    };
+    await client.cancelEmergencyUnlock({
+      locker: lockerAddress,
+      authority: newOwner.publicKey,
+      signers: [newOwner],
+    });
+    await client.transferOwnership({
+      locker: transferredLocker,
+      newOwner: creator,
+      signers: [newOwner],
+    });
+
+    const emergencyUnlock = await client.requestEmergencyUnlock({ locker });
+    let emergencyUnlockAccount = await program.account.emergencyUnlock.fetch(emergencyUnlock);
+    assert.ok(emergencyUnlockAccount.requester.equals(creator));
+    assert.equal(emergencyUnlockAccount.approvedAt, null);
+
+    const unlockEarly = async () => await client.emergencyUnlock({
+      locker,
+      targetWallet: earlyFundingWallet,
+    });
+
+    try {
+      await unlockEarly();
+      assert.ok(false);
+    } catch (err) {
+      assert.equal(err.code, 341);
+    }
+
+    await client.approveEmergencyUnlock(lockerAddress);
+    emergencyUnlockAccount = await program.account.emergencyUnlock.fetch(emergencyUnlock);
+    assert.ok(emergencyUnlockAccount.approvedAt !== null);
+
+    try {
+      await client.approveEmergencyUnlock(lockerAddress);
+      assert.ok(false);
+    } catch (err) {
+      assert.equal(err.code, 342);
+    }
+
+    // the delay hasn't passed yet
+    try {
+      await unlockEarly();
+      assert.ok(false);
+    } catch (err) {
+      assert.equal(err.code, 341);
+    }
+
+    await client.cancelEmergencyUnlock({ locker: lockerAddress });
+    assert.equal(await provider.connection.getAccountInfo(emergencyUnlock), null);
+
+    const lockerAccount = await program.account.locker.fetch(lockerAddress);
+    assert.ok(lockerAccount.depositedAmount.eqn(10000));
+
+    await client.requestEmergencyUnlock({ locker });
+    await client.approveEmergencyUnlock(lockerAddress);
+    // tests are built with `test-instructions` feature where the delay is 5 seconds
+    await lockerClient.utils.sleep(6000);
+    await unlockEarly();
+
+    const fundingWalletAccount = await serumCmn.getTokenAccount(provider, earlyFundingWallet);
+    assert.ok(fundingWalletAccount.amount.eqn(10000));
+    assert.equal(await provider.connection.getAccountInfo(lockerAddress), null);
+    assert.equal(await provider.connection.getAccountInfo(locker.account.vault), null);
+    assert.equal(await provider.connection.getAccountInfo(emergencyUnlock), null);
+  });
+
+  it('Refuses emergency unlock of unlocked locker', async () => {
+    const lateMint = await createMint(provider);
+    const lateFundingWallet = await serumCmn.createTokenAccount(
+      provider,
+      lateMint.publicKey,
+      provider.wallet.publicKey,
+    );
+    await lateMint.mintTo(lateFundingWallet, provider.wallet.publicKey, [], 10000);
+
+    const now = new anchor.BN(Date.now()).divn(1000);
+    const lockerAddress = await client.createLocker({
+      unlockDate: now.addn(3),
+      countryCode: "RU",
+      startEmission: null,
+      amount: new anchor.BN(10000),
+      creator,
+      owner: creator,
+      fundingWalletAuthority: creator,
+      fundingWallet: lateFundingWallet,
+      countryBanlist: countryList.publicKey,
+      feeInSol: true,
+    });
+    const locker = {
+      publicKey: lockerAddress,
+      account: await program.account.locker.fetch(lockerAddress),
+    };
+
+    await client.requestEmergencyUnlock({ locker });
+    await client.approveEmergencyUnlock(lockerAddress);
+    // both the delay and the unlock date pass
+    await lockerClient.utils.sleep(6000);
+
+    await assert.rejects(
+      async () => await client.emergencyUnlock({
+        locker,
+        targetWallet: lateFundingWallet,
+      }),
+      (err: any) => {
+        assert.equal(err.code, 349); // AlreadyUnlocked
+        return true;
+      }
+    );
+
+    await client.cancelEmergencyUnlock({ locker: lockerAddress });
+    await assert.rejects(
+      async () => await client.requestEmergencyUnlock({ locker }),
+      (err: any) => {
+        assert.equal(err.code, 349); // AlreadyUnlocked
+        return true;
+      }
+    );
+
+    await client.withdrawFunds({
+      amount: new anchor.BN(10000),
+      locker,
+      targetWallet: lateFundingWallet,
+    });
+    assert.equal(await provider.connection.getAccountInfo(lockerAddress), null);
+  });
+
+  it('Migrates legacy mint info and locker', async () => {
+    // legacy accounts are loaded from tests/fixtures, see Anchor.toml
+    const legacyOwner = anchor.web3.Keypair.fromSecretKey(
+      Uint8Array.from(JSON.parse(fs.readFileSync('tests/fixtures/legacy-owner.json', 'utf8')))
+    );
+    const legacyMint = new anchor.web3.PublicKey("5ugZKa8gaan5KK1GzZdrtHUxsUgeBNhRehmQKsRrTPZa");
+    const legacyLocker = new anchor.web3.PublicKey("4jQy3DRar5uy1tC32awDTNumGLJSbckcrt3FF5WABsAr");
+
+    // owner tops up rent of the grown locker
+    await provider.send(
+      new anchor.web3.Transaction().add(
+        anchor.web3.SystemProgram.transfer({
+          fromPubkey: provider.wallet.publicKey,
+          toPubkey: legacyOwner.publicKey,
+          lamports: anchor.web3.LAMPORTS_PER_SOL,
+        })
+      )
+    );
+
+    await client.migrateMintInfo(legacyMint);
+    const [mintInfo, _bump] = await client.findMintInfoAddress(legacyMint);
+    let mintInfoAccount = await program.account.mintInfo.fetch(mintInfo);
+    assert.equal(mintInfoAccount.version, 1);
+    assert.ok(mintInfoAccount.feePaid);
+    assert.ok(mintInfoAccount.lockerCount.eqn(0));
+
+    await client.migrateLocker({ locker: legacyLocker, signers: [legacyOwner] });
+    const lockerAccount = await program.account.locker.fetch(legacyLocker);
+    assert.equal(lockerAccount.version, 1);
+    assert.ok(lockerAccount.owner.equals(legacyOwner.publicKey));
+    assert.ok(lockerAccount.creator.equals(legacyOwner.publicKey));
+    assert.ok(lockerAccount.mint.equals(legacyMint));
+    assert.ok(lockerAccount.depositedAmount.eqn(1500));
+    // the vault holds 1000, so 500 were withdrawn
+    assert.ok(lockerAccount.withdrawnAmount.eqn(500));
+
+    mintInfoAccount = await program.account.mintInfo.fetch(mintInfo);
+    assert.ok(mintInfoAccount.lockerCount.eqn(1));
+    assert.ok(mintInfoAccount.totalLocked.eqn(1000));
+
+    try {
+      await client.migrateLocker({ locker: legacyLocker, signers: [legacyOwner] });
+      assert.ok(false);
+    } catch (err) {
+      assert.equal(err.code, 339);
+    }
+  });
+
+  it('Refuses to migrate accounts of the current version', async () => {
+    const [config, _bump] = await client.findConfigAddress();
+    const configAccount = await program.account.config.fetch(config);
+    assert.equal(configAccount.version, 1);
+
+    const [mintInfo, _mintInfoBump] = await client.findMintInfoAddress(mint.publicKey);
+    const mintInfoAccount = await program.account.mintInfo.fetch(mintInfo);
+    assert.equal(mintInfoAccount.version, 1);
+
+    try {
+      await client.migrateConfig();
+      assert.ok(false);
+    } catch (err) {
+      assert.equal(err.code, 339);
+    }
+
+    try {
+      await client.migrateMintInfo(mint.publicKey);
+      assert.ok(false);
+    } catch (err) {
+      assert.equal(err.code, 339);
+    }
+  });
 });
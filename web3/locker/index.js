@@ -1,6 +1,7 @@
 const solana_web3 = require('@solana/web3.js');
 const anchor = require('@project-serum/anchor');
 const serumCmn = require('@project-serum/common');
+const spl = require('@solana/spl-token');
 
 const utils = require('./utils');
 
@@ -17,6 +18,25 @@ const DEVNET = 'devnet';
 const TOKEN_LOCKER = 'token-locker';
 const LP_LOCKER = 'lp-locker';
 
+// Offsets of locker fields usable in `memcmp` filters,
+// including 8 bytes of account discriminator.
+const LOCKER_VERSION = 1;
+const LOCKER_OFFSETS = {
+  VERSION: 8,
+  OWNER: 9,
+  CREATOR: 41,
+  MINT: 73,
+  VAULT: 105,
+};
+
+// Operations a locker delegate can be allowed to, combined with `|`.
+const DELEGATE_PERMISSIONS = {
+  RELOCK: 1,
+  INCREMENT: 2,
+  SPLIT: 4,
+  WITHDRAW: 8,
+};
+
 class Client {
   constructor(provider, programName, cluster) {
     this.provider = provider;
@@ -45,6 +65,174 @@ class Client {
     return [config, bump];
   }
 
+  async findLockerAddress(creator, mint, nonce) {
+    const [locker, bump] = await anchor.web3.PublicKey.findProgramAddress(
+      [
+        new TextEncoder().encode("locker"),
+        creator.toBytes(),
+        mint.toBytes(),
+        nonce.toArray('be', 8),
+      ],
+      this.program.programId
+    );
+    return [locker, bump];
+  }
+
+  // Returns the first nonce without a locker for the creator and the mint.
+  async nextLockerNonce(creator, mint) {
+    let nonce = new anchor.BN(0);
+    for (;;) {
+      const [locker, _bump] = await this.findLockerAddress(creator, mint, nonce);
+      if (await this.provider.connection.getAccountInfo(locker) === null) {
+        return nonce;
+      }
+      nonce = nonce.addn(1);
+    }
+  }
+
+  // Vault is the associated token account of the vault authority.
+  async findVaultAddress(locker, mint, tokenProgram) {
+    const [vaultAuthority, vaultBump] = await anchor.web3.PublicKey.findProgramAddress(
+      [
+        locker.toBytes()
+      ],
+      this.program.programId,
+    );
+    const vault = await utils.associatedAddress(mint, vaultAuthority, tokenProgram);
+    return [vault, vaultAuthority, vaultBump];
+  }
+
+  async findFeeExemptionAddress(wallet) {
+    const [feeExemption, bump] = await anchor.web3.PublicKey.findProgramAddress(
+      [
+        new TextEncoder().encode("fee_exemption"),
+        wallet.toBytes()
+      ],
+      this.program.programId
+    );
+    return [feeExemption, bump];
+  }
+
+  async findReferrerAddress(wallet) {
+    const [referrer, bump] = await anchor.web3.PublicKey.findProgramAddress(
+      [
+        new TextEncoder().encode("referrer"),
+        wallet.toBytes()
+      ],
+      this.program.programId
+    );
+    return [referrer, bump];
+  }
+
+  async findEmergencyUnlockAddress(locker) {
+    const [emergencyUnlock, bump] = await anchor.web3.PublicKey.findProgramAddress(
+      [
+        new TextEncoder().encode("emergency_unlock"),
+        locker.toBytes()
+      ],
+      this.program.programId
+    );
+    return [emergencyUnlock, bump];
+  }
+
+  // Returns fee exemption account as a list of remaining accounts
+  // if there's one for the wallet.
+  async feeExemptionAccounts(wallet) {
+    const [feeExemption, _bump] = await this.findFeeExemptionAddress(wallet);
+
+    return await tryIfExists(
+      this.program, "feeExemption", feeExemption,
+      (_feeExemptionAccount) => [{ pubkey: feeExemption, isWritable: false, isSigner: false }],
+      () => [],
+    );
+  }
+
+  // Returns wallet getting part of the fee and its associated token account
+  // as remaining accounts, with instructions creating the latter if fees
+  // are paid in tokens.
+  async feeShareAccounts(wallet, mint, tokenProgram, paysInTokens) {
+    const accounts = [{ pubkey: wallet, isWritable: true, isSigner: false }];
+    if (!paysInTokens) {
+      return [accounts, []];
+    }
+
+    const [tokenWallet, instrs] = await utils.getOrCreateAssociatedTokenAccountInstrs(
+      this.provider, mint, wallet, tokenProgram
+    );
+    accounts.push({ pubkey: tokenWallet, isWritable: true, isSigner: false });
+    return [accounts, instrs];
+  }
+
+  // Fee share accounts of the referrer, if there's one, and of fee recipients from config.
+  async feeSharesAccounts(configAccount, referrer, mint, tokenProgram, paysInTokens) {
+    const wallets = configAccount.feeRecipients.map((recipient) => recipient.wallet);
+    if (referrer !== null && !wallets.some((wallet) => wallet.equals(referrer))) {
+      wallets.push(referrer);
+    }
+
+    let accounts = [];
+    let instrs = [];
+    if (referrer !== null) {
+      // proves the referrer is registered by admin
+      const [referrerAccount, _bump] = await this.findReferrerAddress(referrer);
+      accounts.push({ pubkey: referrerAccount, isWritable: false, isSigner: false });
+    }
+    for (const wallet of wallets) {
+      const [walletAccounts, walletInstrs] = await this.feeShareAccounts(
+        wallet, mint, tokenProgram, paysInTokens
+      );
+      accounts = accounts.concat(walletAccounts);
+      instrs = instrs.concat(walletInstrs);
+    }
+    return [accounts, instrs];
+  }
+
+  // Stable fee mint, its token program, payer's token account and fee share
+  // accounts in stable token as remaining accounts.
+  async stableFeeAccounts(configAccount, referrer, payer) {
+    const mint = configAccount.stableFeeMint;
+    const tokenProgram = await utils.getTokenProgram(this.provider, mint);
+    const payerTokenWallet = await utils.associatedAddress(mint, payer, tokenProgram);
+    const [feeTokenWallet, createFeeTokenWalletInstrs] = await utils.getOrCreateAssociatedTokenAccountInstrs(
+      this.provider, mint, configAccount.feeWallet, tokenProgram
+    );
+    const [feeSharesAccounts, createFeeSharesInstrs] = await this.feeSharesAccounts(
+      configAccount, referrer, mint, tokenProgram, true
+    );
+
+    const accounts = [
+      { pubkey: mint, isWritable: false, isSigner: false },
+      { pubkey: tokenProgram, isWritable: false, isSigner: false },
+      { pubkey: payerTokenWallet, isWritable: true, isSigner: false },
+      { pubkey: feeTokenWallet, isWritable: true, isSigner: false },
+    ].concat(feeSharesAccounts);
+    return [accounts, createFeeTokenWalletInstrs.concat(createFeeSharesInstrs)];
+  }
+
+  // Returns the account managing the locker and remaining accounts proving it.
+  // Locker with receipt is managed by the receipt holder and locker with
+  // beneficiaries is withdrawn by one of them, provider wallet by default.
+  async lockerAuthority(locker, holder, delegate) {
+    if (delegate !== undefined) {
+      return [delegate, []];
+    }
+    if (holder === undefined) {
+      holder = this.provider.wallet.publicKey;
+    }
+    if (locker.account.beneficiaries.length > 0) {
+      return [holder, []];
+    }
+    if (locker.account.receiptMint === null) {
+      return [locker.account.owner, []];
+    }
+
+    const tokenProgram = await utils.getTokenProgram(this.provider, locker.account.receiptMint);
+    const receiptAccount = await utils.associatedAddress(
+      locker.account.receiptMint, holder, tokenProgram
+    );
+    return [holder, [{ pubkey: receiptAccount, isWritable: false, isSigner: false }]];
+  }
+
   async vaultAuthorityAddress(locker) {
     return await anchor.web3.PublicKey.createProgramAddress(
       [
@@ -94,23 +282,28 @@ class Client {
   }
 
   async createLocker(args) {
-    const locker = anchor.web3.Keypair.generate();
+    const fundingWalletAccount = await serumCmn.getTokenAccount(this.provider, args.fundingWallet);
+    const tokenProgram = await utils.getTokenProgram(this.provider, fundingWalletAccount.mint);
 
-    const [vaultAuthority, vaultBump] = await anchor.web3.PublicKey.findProgramAddress(
-      [
-        locker.publicKey.toBytes()
-      ],
-      this.program.programId,
+    const nonce = args.nonce === undefined
+      ? await this.nextLockerNonce(args.creator, fundingWalletAccount.mint)
+      : args.nonce;
+    const [locker, lockerBump] = await this.findLockerAddress(
+      args.creator, fundingWalletAccount.mint, nonce
     );
-
-    const fundingWalletAccount = await serumCmn.getTokenAccount(this.provider, args.fundingWallet);
-    const vault = new anchor.web3.Account();
-    const createTokenAccountInstrs = await serumCmn.createTokenAccountInstrs(
-      this.provider,
-      vault.publicKey,
-      fundingWalletAccount.mint,
-      vaultAuthority
+    const [vault, vaultAuthority, vaultBump] = await this.findVaultAddress(
+      locker, fundingWalletAccount.mint, tokenProgram
     );
+    const createTokenAccountInstrs = [
+      spl.Token.createAssociatedTokenAccountInstruction(
+        spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+        tokenProgram,
+        fundingWalletAccount.mint,
+        vault,
+        vaultAuthority,
+        this.provider.wallet.publicKey,
+      ),
+    ];
 
     const [config, _] = await this.findConfigAddress();
     const configAccount = await this.program.account.config.fetch(config);
@@ -122,84 +315,244 @@ class Client {
       config
     );
     const [feeTokenWallet, createAssociatedTokenAccountInstrs] = await utils.getOrCreateAssociatedTokenAccountInstrs(
-      this.provider, fundingWalletAccount.mint, configAccount.feeWallet
+      this.provider, fundingWalletAccount.mint, configAccount.feeWallet, tokenProgram
     );
+    const referrer = args.referrer === undefined ? null : args.referrer;
+    const feeInStable = args.feeInStable === undefined ? false : args.feeInStable;
+    let [feeSharesAccounts, createFeeSharesInstrs] = await this.feeSharesAccounts(
+      configAccount,
+      referrer,
+      fundingWalletAccount.mint,
+      tokenProgram,
+      configAccount.mintInfoPermissioned || !args.feeInSol,
+    );
+    if (feeInStable) {
+      const [stableFeeAccounts, createStableFeeInstrs] = await this.stableFeeAccounts(
+        configAccount, referrer, args.creator
+      );
+      feeSharesAccounts = feeSharesAccounts.concat(stableFeeAccounts);
+      createFeeSharesInstrs = createFeeSharesInstrs.concat(createStableFeeInstrs);
+    }
+    if (configAccount.solPriceFeed !== null && !feeInStable) {
+      // SOL fee is priced in USD
+      feeSharesAccounts.push({
+        pubkey: configAccount.solPriceFeed.account, isWritable: false, isSigner: false
+      });
+    }
+    let remainingAccounts = (await this.feeExemptionAccounts(args.creator))
+      .concat(feeSharesAccounts);
+    let createReceiptInstrs = [];
+    const signers = [];
+
+    if (args.receipt) {
+      const receiptMint = anchor.web3.Keypair.generate();
+      const receiptAccount = await utils.associatedAddress(
+        receiptMint.publicKey, args.owner, tokenProgram
+      );
+
+      createReceiptInstrs = [
+        anchor.web3.SystemProgram.createAccount({
+          fromPubkey: this.provider.wallet.publicKey,
+          newAccountPubkey: receiptMint.publicKey,
+          space: spl.MintLayout.span,
+          lamports: await this.provider.connection.getMinimumBalanceForRentExemption(
+            spl.MintLayout.span
+          ),
+          programId: tokenProgram,
+        }),
+        // vault authority mints the only receipt token and drops the authority
+        spl.Token.createInitMintInstruction(
+          tokenProgram, receiptMint.publicKey, 0, vaultAuthority, null
+        ),
+        spl.Token.createAssociatedTokenAccountInstruction(
+          spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+          tokenProgram,
+          receiptMint.publicKey,
+          receiptAccount,
+          args.owner,
+          this.provider.wallet.publicKey,
+        ),
+      ];
+      remainingAccounts = [
+        { pubkey: receiptMint.publicKey, isWritable: true, isSigner: false },
+        { pubkey: receiptAccount, isWritable: true, isSigner: false },
+      ].concat(remainingAccounts);
+      signers.push(receiptMint);
+    }
 
     await this.program.rpc.createLocker(
       {
         unlockDate: args.unlockDate,
         vaultBump,
+        nonce,
+        lockerBump,
         countryCode: args.countryCode,
         startEmission: args.startEmission,
+        cliff: args.cliff === undefined ? null : args.cliff,
+        schedule: args.schedule === undefined ? [] : args.schedule,
         amount: args.amount,
         feeInSol: args.feeInSol,
+        feeInStable,
+        receipt: args.receipt === undefined ? false : args.receipt,
+        beneficiary: args.beneficiary === undefined ? null : args.beneficiary,
+        revocable: args.revocable === undefined ? false : args.revocable,
+        beneficiaries: args.beneficiaries === undefined ? [] : args.beneficiaries,
+        referrer,
       },
       {
         accounts: {
-          locker: locker.publicKey,
+          locker,
           creator: args.creator,
           owner: args.owner,
-          vault: vault.publicKey,
+          vault,
           vaultAuthority,
           fundingWalletAuthority: args.fundingWalletAuthority,
           fundingWallet: args.fundingWallet,
           feeWallet: configAccount.feeWallet,
           feeTokenWallet,
+          mint: fundingWalletAccount.mint,
           mintInfo,
           countryBanlist: configAccount.countryList,
           config,
 
           clock: anchor.web3.SYSVAR_CLOCK_PUBKEY,
           systemProgram: anchor.web3.SystemProgram.programId,
-          tokenProgram: utils.TOKEN_PROGRAM_ID,
+          tokenProgram,
         },
+        remainingAccounts,
         instructions: createTokenAccountInstrs
           .concat(initMintInfoInstrs)
-          .concat(createAssociatedTokenAccountInstrs),
-        signers: [vault, locker],
+          .concat(createAssociatedTokenAccountInstrs)
+          .concat(createFeeSharesInstrs)
+          .concat(createReceiptInstrs),
+        signers,
       }
     );
 
-    return locker.publicKey;
+    return locker;
+  }
+
+  // Returns aggregate stats of lockers of the mint, null if it has no mint info.
+  async getMintStats(mint) {
+    const [mintInfo, _bump] = await this.findMintInfoAddress(mint);
+
+    return await tryIfExists(
+      this.program, "mintInfo", mintInfo,
+      (mintInfoAccount) => {
+        const totalLocked = mintInfoAccount.totalLocked;
+        const lockerCount = mintInfoAccount.lockerCount;
+        return {
+          totalLocked,
+          revocableLocked: mintInfoAccount.revocableLocked,
+          lockerCount,
+          unlockDateLowerBound: lockerCount.isZero() ? null : mintInfoAccount.unlockDateLowerBound,
+          averageUnlockDate: totalLocked.isZero()
+            ? null
+            : mintInfoAccount.weightedUnlockSum.div(totalLocked),
+        };
+      },
+      () => null,
+    );
   }
 
   async getLockers() {
     return await this.program.account.locker.all();
   }
 
-  async getLockersOwnedBy(owner) {
-    if (owner === undefined) {
-      owner = this.provider.wallet.publicKey;
-    }
-    return await this.program.account.locker.all([
+  // Returns lockers of the current layout version with the given keys
+  // at given offsets, e.g. `{ [LOCKER_OFFSETS.MINT]: mint }`.
+  async getLockersBy(keys) {
+    const filters = [
       {
         memcmp: {
-          // 8 bytes for discriminator
-          offset: 8,
-          bytes: owner.toBase58(),
+          offset: LOCKER_OFFSETS.VERSION,
+          bytes: anchor.utils.bytes.bs58.encode([LOCKER_VERSION]),
         },
       },
-    ]);
+    ];
+    for (const [offset, key] of Object.entries(keys)) {
+      filters.push({
+        memcmp: {
+          offset: Number(offset),
+          bytes: key.toBase58(),
+        },
+      });
+    }
+    return await this.program.account.locker.all(filters);
+  }
+
+  async getLockersOwnedBy(owner, mint) {
+    if (owner === undefined) {
+      owner = this.provider.wallet.publicKey;
+    }
+    const keys = { [LOCKER_OFFSETS.OWNER]: owner };
+    if (mint !== undefined) {
+      keys[LOCKER_OFFSETS.MINT] = mint;
+    }
+    return await this.getLockersBy(keys);
+  }
+
+  async getLockersByMint(mint) {
+    return await this.getLockersBy({ [LOCKER_OFFSETS.MINT]: mint });
+  }
+
+  // Returns lockers where the account has a share.
+  async getLockersWithBeneficiary(beneficiary) {
+    if (beneficiary === undefined) {
+      beneficiary = this.provider.wallet.publicKey;
+    }
+    const lockers = await this.program.account.locker.all();
+    return lockers.filter((locker) =>
+      locker.account.beneficiaries.some((b) => b.owner.equals(beneficiary))
+    );
+  }
+
+  async getLockersCreatedBy(creator) {
+    if (creator === undefined) {
+      creator = this.provider.wallet.publicKey;
+    }
+    return await this.getLockersBy({ [LOCKER_OFFSETS.CREATOR]: creator });
   }
 
   async relock(args) {
+    // only part of the tokens is split off into a new locker and relocked
+    if (args.amount !== undefined) {
+      return await this.splitLocker({
+        ...args,
+        newOwner: args.receiptHolder === undefined ? args.locker.account.owner : args.receiptHolder,
+      });
+    }
+
+    const [owner, remainingAccounts] = await this.lockerAuthority(
+      args.locker, args.receiptHolder, args.delegate
+    );
+    const [mintInfo, _bump] = await this.findMintInfoAddress(args.locker.account.mint);
+
     return await this.program.rpc.relock(
       args.unlockDate,
       {
         accounts: {
           locker: args.locker.publicKey,
-          owner: args.locker.account.owner,
-        }
+          owner,
+          mintInfo,
+
+          clock: anchor.web3.SYSVAR_CLOCK_PUBKEY,
+        },
+        remainingAccounts,
       }
     );
   }
 
   async transferOwnership(args) {
+    const [config, _] = await this.findConfigAddress();
+
     const rpcArgs = {
       accounts: {
         locker: args.locker.publicKey,
-        owner: args.locker.account.owner,
+        // beneficiary transferring its share for lockers with beneficiaries
+        owner: args.owner === undefined ? args.locker.account.owner : args.owner,
         newOwner: args.newOwner,
+        config,
       }
     };
 
@@ -210,22 +563,69 @@ class Client {
     return await this.program.rpc.transferOwnership(rpcArgs);
   }
 
+  async setBeneficiary(args) {
+    const [config, _] = await this.findConfigAddress();
+    const [owner, remainingAccounts] = await this.lockerAuthority(args.locker, args.receiptHolder);
+
+    return await this.program.rpc.setBeneficiary(
+      args.beneficiary,
+      {
+        accounts: {
+          locker: args.locker.publicKey,
+          owner,
+          config,
+        },
+        remainingAccounts,
+      }
+    );
+  }
+
+  async setDelegate(args) {
+    const [config, _] = await this.findConfigAddress();
+
+    return await this.program.rpc.setDelegate(
+      args.delegate,
+      args.delegate === null ? 0 : args.permissions,
+      {
+        accounts: {
+          locker: args.locker.publicKey,
+          owner: args.locker.account.owner,
+          config,
+        },
+        signers: args.signers === undefined ? [] : args.signers,
+      }
+    );
+  }
+
   async incrementLock(args) {
     const [config, _] = await this.findConfigAddress();
     const configAccount = await this.program.account.config.fetch(config);
 
     const fundingWalletAccount = await serumCmn.getTokenAccount(this.provider, args.fundingWallet);
+    const tokenProgram = await utils.getTokenProgram(this.provider, fundingWalletAccount.mint);
     const [mintInfo, initMintInfoInstrs] = await getOrCreateMintInfo(
       this.program,
       fundingWalletAccount.mint,
       args.fundingWalletAuthority
     );
     const [feeTokenWallet, createAssociatedTokenAccountInstrs] = await utils.getOrCreateAssociatedTokenAccountInstrs(
-      this.provider, fundingWalletAccount.mint, configAccount.feeWallet
+      this.provider, fundingWalletAccount.mint, configAccount.feeWallet, tokenProgram
+    );
+    // receipt holder proves it holds the receipt
+    const [_authority, receiptAccounts] = await this.lockerAuthority(
+      args.locker, args.fundingWalletAuthority
     );
+    let remainingAccounts = (await this.feeExemptionAccounts(args.locker.account.creator))
+      .concat(receiptAccounts);
+    const referrer = args.referrer === undefined ? null : args.referrer;
+    const [feeSharesAccounts, createFeeSharesInstrs] = await this.feeSharesAccounts(
+      configAccount, referrer, fundingWalletAccount.mint, tokenProgram, true
+    );
+    remainingAccounts = remainingAccounts.concat(feeSharesAccounts);
 
     await this.program.rpc.incrementLock(
       args.amount,
+      referrer,
       {
         accounts: {
           locker: args.locker.publicKey,
@@ -233,12 +633,17 @@ class Client {
           fundingWallet: args.fundingWallet,
           fundingWalletAuthority: args.fundingWalletAuthority,
           feeWallet: feeTokenWallet,
-          tokenProgram: utils.TOKEN_PROGRAM_ID,
+          tokenProgram,
+          mint: fundingWalletAccount.mint,
           mintInfo,
-          config
+          config,
+          clock: anchor.web3.SYSVAR_CLOCK_PUBKEY,
         },
+        remainingAccounts,
         instructions: initMintInfoInstrs
           .concat(createAssociatedTokenAccountInstrs)
+          .concat(createFeeSharesInstrs),
+        signers: args.signers === undefined ? [] : args.signers,
       }
     );
 
@@ -257,28 +662,42 @@ class Client {
     let targetWallet = args.targetWallet;
     let extraInstructions = [];
 
+    const vaultWalletAccount = await serumCmn.getTokenAccount(this.provider, args.locker.account.vault);
+    const tokenProgram = await utils.getTokenProgram(this.provider, vaultWalletAccount.mint);
+
     if (args.createAssociated) {
-      const vaultWalletAccount = await serumCmn.getTokenAccount(this.provider, args.locker.account.vault);
       const [targetTokenWallet, createAssociatedTokenAccountInstrs] = await utils.getOrCreateAssociatedTokenAccountInstrs(
-        this.provider, vaultWalletAccount.mint, targetWallet
+        this.provider, vaultWalletAccount.mint, targetWallet, tokenProgram
       );
       targetWallet = targetTokenWallet;
       extraInstructions.concat(createAssociatedTokenAccountInstrs);
     }
 
+    const [config, _] = await this.findConfigAddress();
+    const [mintInfo, _bump] = await this.findMintInfoAddress(vaultWalletAccount.mint);
+    const [owner, remainingAccounts] = await this.lockerAuthority(
+      args.locker, args.receiptHolder || args.beneficiary, args.delegate
+    );
+
     await this.program.rpc.withdrawFunds(
       args.amount,
       {
+        signers: args.signers === undefined ? [] : args.signers,
         accounts: {
           locker: args.locker.publicKey,
-          owner: args.locker.account.owner,
+          owner,
+          lockerOwner: args.locker.account.owner,
           vaultAuthority,
           vault: args.locker.account.vault,
+          mint: vaultWalletAccount.mint,
+          mintInfo,
           targetWallet,
+          config,
 
           clock: anchor.web3.SYSVAR_CLOCK_PUBKEY,
-          tokenProgram: utils.TOKEN_PROGRAM_ID,
+          tokenProgram,
         },
+        remainingAccounts,
         instructions: extraInstructions
       }
     );
@@ -286,6 +705,47 @@ class Client {
     return targetWallet;
   }
 
+  async revoke(args) {
+    const vaultAuthority = await this.vaultAuthorityAddress(args.locker);
+    const vaultAccount = await serumCmn.getTokenAccount(this.provider, args.locker.account.vault);
+    const tokenProgram = await utils.getTokenProgram(this.provider, vaultAccount.mint);
+
+    let targetWallet = args.targetWallet;
+    let extraInstructions = [];
+
+    if (targetWallet === undefined) {
+      const [creatorTokenWallet, createAssociatedTokenAccountInstrs] = await utils.getOrCreateAssociatedTokenAccountInstrs(
+        this.provider, vaultAccount.mint, args.locker.account.creator, tokenProgram
+      );
+      targetWallet = creatorTokenWallet;
+      extraInstructions = createAssociatedTokenAccountInstrs;
+    }
+
+    const [config, _] = await this.findConfigAddress();
+    const [mintInfo, _bump] = await this.findMintInfoAddress(vaultAccount.mint);
+
+    await this.program.rpc.revoke(
+      {
+        accounts: {
+          locker: args.locker.publicKey,
+          creator: args.locker.account.creator,
+          vaultAuthority,
+          vault: args.locker.account.vault,
+          mint: vaultAccount.mint,
+          mintInfo,
+          targetWallet,
+          config,
+
+          clock: anchor.web3.SYSVAR_CLOCK_PUBKEY,
+          tokenProgram,
+        },
+        instructions: extraInstructions,
+      }
+    );
+
+    return targetWallet;
+  }
+
   async closeLocker(args) {
     const vaultAuthority = await anchor.web3.PublicKey.createProgramAddress(
       [
@@ -295,16 +755,22 @@ class Client {
       this.program.programId,
     );
 
-    await this.program.rpc.withdrawFunds(
+    const vaultWalletAccount = await serumCmn.getTokenAccount(this.provider, args.locker.account.vault);
+    const tokenProgram = await utils.getTokenProgram(this.provider, vaultWalletAccount.mint);
+    const [mintInfo, _bump] = await this.findMintInfoAddress(vaultWalletAccount.mint);
+
+    await this.program.rpc.closeLocker(
       {
         accounts: {
           locker: args.locker.publicKey,
           owner: args.locker.account.owner,
           vaultAuthority,
           vault: args.locker.account.vault,
+          mint: vaultWalletAccount.mint,
+          mintInfo,
           targetWallet: args.targetWallet,
 
-          tokenProgram: utils.TOKEN_PROGRAM_ID,
+          tokenProgram,
         }
       }
     );
@@ -312,6 +778,98 @@ class Client {
     return vaultAuthority;
   }
 
+  // Asks admin to let the owner withdraw everything before the unlock date.
+  async requestEmergencyUnlock(args) {
+    const [config, _configBump] = await this.findConfigAddress();
+    const [emergencyUnlock, bump] = await this.findEmergencyUnlockAddress(args.locker.publicKey);
+    const [owner, remainingAccounts] = await this.lockerAuthority(args.locker, args.receiptHolder);
+
+    await this.program.rpc.requestEmergencyUnlock(
+      bump,
+      {
+        signers: args.signers === undefined ? [] : args.signers,
+        accounts: {
+          locker: args.locker.publicKey,
+          owner,
+          emergencyUnlock,
+          config,
+
+          clock: anchor.web3.SYSVAR_CLOCK_PUBKEY,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        },
+        remainingAccounts,
+      }
+    );
+
+    return emergencyUnlock;
+  }
+
+  // Admin only, funds can be withdrawn after the mandatory delay.
+  async approveEmergencyUnlock(locker) {
+    const [config, _configBump] = await this.findConfigAddress();
+    const [emergencyUnlock, _bump] = await this.findEmergencyUnlockAddress(locker);
+
+    await this.program.rpc.approveEmergencyUnlock({
+      accounts: {
+        admin: this.provider.wallet.publicKey,
+        config,
+        emergencyUnlock,
+
+        clock: anchor.web3.SYSVAR_CLOCK_PUBKEY,
+      }
+    });
+  }
+
+  // Either the requester, current owner of the locker or admin can cancel.
+  async cancelEmergencyUnlock(args) {
+    const [config, _configBump] = await this.findConfigAddress();
+    const [emergencyUnlock, _bump] = await this.findEmergencyUnlockAddress(args.locker);
+    const emergencyUnlockAccount = await this.program.account.emergencyUnlock.fetch(emergencyUnlock);
+
+    await this.program.rpc.cancelEmergencyUnlock({
+      signers: args.signers === undefined ? [] : args.signers,
+      accounts: {
+        authority: args.authority === undefined ? this.provider.wallet.publicKey : args.authority,
+        locker: args.locker,
+        config,
+        emergencyUnlock,
+        requester: emergencyUnlockAccount.requester,
+      }
+    });
+  }
+
+  async emergencyUnlock(args) {
+    const vaultAuthority = await this.vaultAuthorityAddress(args.locker);
+    const vaultAccount = await serumCmn.getTokenAccount(this.provider, args.locker.account.vault);
+    const tokenProgram = await utils.getTokenProgram(this.provider, vaultAccount.mint);
+
+    const [config, _configBump] = await this.findConfigAddress();
+    const [mintInfo, _mintInfoBump] = await this.findMintInfoAddress(vaultAccount.mint);
+    const [emergencyUnlock, _bump] = await this.findEmergencyUnlockAddress(args.locker.publicKey);
+    const [owner, remainingAccounts] = await this.lockerAuthority(args.locker, args.receiptHolder);
+
+    await this.program.rpc.emergencyUnlock(
+      {
+        signers: args.signers === undefined ? [] : args.signers,
+        accounts: {
+          locker: args.locker.publicKey,
+          owner,
+          emergencyUnlock,
+          vaultAuthority,
+          vault: args.locker.account.vault,
+          mint: vaultAccount.mint,
+          mintInfo,
+          targetWallet: args.targetWallet,
+          config,
+
+          clock: anchor.web3.SYSVAR_CLOCK_PUBKEY,
+          tokenProgram,
+        },
+        remainingAccounts,
+      }
+    );
+  }
+
   async splitLocker(args) {
     const oldVaultAuthority = await anchor.web3.PublicKey.createProgramAddress(
       [
@@ -330,20 +888,26 @@ class Client {
       this.program.programId
     );
 
-    const [newVaultAuthority, newVaultBump] = await anchor.web3.PublicKey.findProgramAddress(
-      [
-        newLocker.toBytes(),
-      ],
-      this.program.programId,
+    const vaultAccount = await serumCmn.getTokenAccount(this.provider, args.locker.account.vault);
+    const tokenProgram = await utils.getTokenProgram(this.provider, vaultAccount.mint);
+    const [newVault, newVaultAuthority, newVaultBump] = await this.findVaultAddress(
+      newLocker, vaultAccount.mint, tokenProgram
     );
+    const createTokenAccountInstrs = [
+      spl.Token.createAssociatedTokenAccountInstruction(
+        spl.ASSOCIATED_TOKEN_PROGRAM_ID,
+        tokenProgram,
+        vaultAccount.mint,
+        newVault,
+        newVaultAuthority,
+        this.provider.wallet.publicKey,
+      ),
+    ];
 
-    const vaultAccount = await serumCmn.getTokenAccount(this.provider, args.locker.account.vault);
-    const newVault = new anchor.web3.Account();
-    const createTokenAccountInstrs = await serumCmn.createTokenAccountInstrs(
-      this.provider,
-      newVault.publicKey,
-      vaultAccount.mint,
-      newVaultAuthority
+    const [config, _] = await this.findConfigAddress();
+    const [mintInfo, _bump] = await this.findMintInfoAddress(vaultAccount.mint);
+    const [oldOwner, remainingAccounts] = await this.lockerAuthority(
+      args.locker, args.receiptHolder, args.delegate
     );
 
     await this.program.rpc.splitLocker(
@@ -351,29 +915,131 @@ class Client {
         amount: args.amount,
         lockerBump: newLockerBump,
         vaultBump: newVaultBump,
+        unlockDate: args.unlockDate === undefined ? null : args.unlockDate,
       },
       {
         accounts: {
           oldLocker: args.locker.publicKey,
-          oldOwner: args.locker.account.owner,
+          oldOwner,
+          lockerOwner: args.locker.account.owner,
           oldVaultAuthority,
           oldVault: args.locker.account.vault,
+          mint: vaultAccount.mint,
+          mintInfo,
 
           newLocker,
           newOwner: args.newOwner,
           newVaultAuthority,
-          newVault: newVault.publicKey,
+          newVault,
+          config,
 
+          clock: anchor.web3.SYSVAR_CLOCK_PUBKEY,
           systemProgram: anchor.web3.SystemProgram.programId,
-          tokenProgram: utils.TOKEN_PROGRAM_ID,
+          tokenProgram,
         },
+        remainingAccounts,
         instructions: createTokenAccountInstrs,
-        signers: [newVault],
       }
     );
 
     return newVault;
   }
+
+  async mergeLockers(args) {
+    const sourceVaultAuthority = await this.vaultAuthorityAddress(args.source);
+    const [owner, sourceAccounts] = await this.lockerAuthority(args.source, args.receiptHolder);
+    const [_owner, destinationAccounts] = await this.lockerAuthority(args.destination, args.receiptHolder);
+
+    const vaultAccount = await serumCmn.getTokenAccount(this.provider, args.source.account.vault);
+    const tokenProgram = await utils.getTokenProgram(this.provider, vaultAccount.mint);
+
+    const [config, _] = await this.findConfigAddress();
+    const [mintInfo, _bump] = await this.findMintInfoAddress(vaultAccount.mint);
+
+    await this.program.rpc.mergeLockers(
+      {
+        accounts: {
+          sourceLocker: args.source.publicKey,
+          destinationLocker: args.destination.publicKey,
+          owner,
+          sourceVaultAuthority,
+          sourceVault: args.source.account.vault,
+          destinationVault: args.destination.account.vault,
+          mint: vaultAccount.mint,
+          mintInfo,
+          config,
+
+          tokenProgram,
+        },
+        remainingAccounts: sourceAccounts.concat(destinationAccounts),
+      }
+    );
+  }
+
+  // Upgrades config created before accounts had a version, admin only.
+  async migrateConfig() {
+    const [config, _bump] = await this.findConfigAddress();
+
+    return await this.program.rpc.migrateConfig({
+      accounts: {
+        admin: this.provider.wallet.publicKey,
+        config,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      }
+    });
+  }
+
+  // Upgrades mint info created before accounts had a version, it should be
+  // done before upgrading lockers of the mint.
+  async migrateMintInfo(mint) {
+    const [config, _configBump] = await this.findConfigAddress();
+    const [mintInfo, _bump] = await this.findMintInfoAddress(mint);
+
+    return await this.program.rpc.migrateMintInfo({
+      accounts: {
+        payer: this.provider.wallet.publicKey,
+        mintInfo,
+        mint,
+        config,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      }
+    });
+  }
+
+  // Upgrades locker created before accounts had a version, `locker` is its
+  // `anchor.web3.PublicKey` since it can't be fetched until upgraded.
+  // The owner should sign, provider wallet by default.
+  async migrateLocker(args) {
+    const lockerAccount = await this.provider.connection.getAccountInfo(args.locker);
+    const legacyLocker = parseLegacyLocker(lockerAccount.data);
+    const vaultAccount = await serumCmn.getTokenAccount(this.provider, legacyLocker.vault);
+    const [mintInfo, _bump] = await this.findMintInfoAddress(vaultAccount.mint);
+
+    return await this.program.rpc.migrateLocker({
+      accounts: {
+        locker: args.locker,
+        owner: legacyLocker.owner,
+        vault: legacyLocker.vault,
+        mint: vaultAccount.mint,
+        mintInfo,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      },
+      signers: args.signers === undefined ? [] : args.signers,
+    });
+  }
+}
+
+// Owner and vault of locker created before accounts had a version.
+function parseLegacyLocker(data) {
+  // discriminator, owner, country code and unlock date
+  let offset = 8 + 32 + 2 + 8;
+  const owner = new anchor.web3.PublicKey(data.slice(8, 40));
+  // start emission is `Option<i64>`, deposited amount follows it
+  offset += data[offset] === 0 ? 1 : 9;
+  offset += 8;
+  const vault = new anchor.web3.PublicKey(data.slice(offset, offset + 32));
+
+  return { owner, vault };
 }
 
 function initProgram(provider, cluster, program) {
@@ -564,8 +1230,7 @@ async function getLockersOwnedBy(provider, owner, cluster, programName) {
   return await program.account.locker.all([
     {
       memcmp: {
-        // 8 bytes for discriminator
-        offset: 8,
+        offset: LOCKER_OFFSETS.OWNER,
         bytes: owner.toBase58(),
       },
     },
@@ -782,6 +1447,8 @@ module.exports = {
   DEVNET,
   LP_LOCKER,
   TOKEN_LOCKER,
+  DELEGATE_PERMISSIONS,
+  LOCKER_OFFSETS,
   Client,
   findMintInfoAddress,
   findConfigAddress,
@@ -7,6 +7,54 @@ const serumCmn = require('@project-serum/common');
 const TOKEN_PROGRAM_ID = new anchor.web3.PublicKey(
   TokenInstructions.TOKEN_PROGRAM_ID.toString()
 );
+const TOKEN_2022_PROGRAM_ID = new anchor.web3.PublicKey(
+  "TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb"
+);
+
+// Returns the token program (SPL Token or Token-2022) owning the mint.
+async function getTokenProgram(provider, mint) {
+  const mintAccount = await provider.connection.getAccountInfo(mint);
+  if (mintAccount === null) {
+    throw new Error("Mint " + mint.toBase58() + " does not exist");
+  }
+  return mintAccount.owner;
+}
+
+async function createTokenAccountInstrs(provider, newAccount, mint, owner, tokenProgram) {
+  if (tokenProgram === undefined) {
+    tokenProgram = TOKEN_PROGRAM_ID;
+  }
+  const lamports = await provider.connection.getMinimumBalanceForRentExemption(
+    spl.AccountLayout.span
+  );
+
+  return [
+    web3.SystemProgram.createAccount({
+      fromPubkey: provider.wallet.publicKey,
+      newAccountPubkey: newAccount,
+      space: spl.AccountLayout.span,
+      lamports,
+      programId: tokenProgram,
+    }),
+    spl.Token.createInitAccountInstruction(
+      tokenProgram,
+      mint,
+      newAccount,
+      owner,
+    ),
+  ];
+}
+
+async function associatedAddress(mint, owner, tokenProgram) {
+  if (tokenProgram === undefined) {
+    tokenProgram = TOKEN_PROGRAM_ID;
+  }
+  const [address, _bump] = await web3.PublicKey.findProgramAddress(
+    [owner.toBytes(), tokenProgram.toBytes(), mint.toBytes()],
+    spl.ASSOCIATED_TOKEN_PROGRAM_ID
+  );
+  return address;
+}
 
 async function createTokenAccount(provider, mint, owner) {
   if (owner === undefined) {
@@ -56,8 +104,11 @@ async function createTokenAccount(provider, mint, owner) {
 const FAILED_TO_FIND_ACCOUNT = 'Failed to find token account';
 const INVALID_ACCOUNT_OWNER = 'Invalid account owner';
 
-async function getOrCreateAssociatedTokenAccountInstrs(provider, mint, owner) {
-  let associatedTokenAddress = await anchor.utils.token.associatedAddress({ mint, owner });
+async function getOrCreateAssociatedTokenAccountInstrs(provider, mint, owner, tokenProgram) {
+  if (tokenProgram === undefined) {
+    tokenProgram = TOKEN_PROGRAM_ID;
+  }
+  let associatedTokenAddress = await associatedAddress(mint, owner, tokenProgram);
 
   try {
     const _ = await serumCmn.getTokenAccount(provider, associatedTokenAddress);
@@ -73,7 +124,7 @@ async function getOrCreateAssociatedTokenAccountInstrs(provider, mint, owner) {
     ) {
       let createTokenAccountInstr = spl.Token.createAssociatedTokenAccountInstruction(
         spl.ASSOCIATED_TOKEN_PROGRAM_ID,
-        TOKEN_PROGRAM_ID,
+        tokenProgram,
         mint,
         associatedTokenAddress,
         owner,
@@ -92,7 +143,11 @@ function sleep(ms) {
 
 module.exports = {
   createTokenAccount,
+  createTokenAccountInstrs,
+  associatedAddress,
   getOrCreateAssociatedTokenAccountInstrs,
+  getTokenProgram,
   sleep,
   TOKEN_PROGRAM_ID,
+  TOKEN_2022_PROGRAM_ID,
 };